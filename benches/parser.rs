@@ -0,0 +1,85 @@
+// Time the streaming parse of `WikitextTableParser::from_reader` on long
+// texts, against the parse of the same text as a string, and the parse of
+// a cell of many unclosed tags. The time should grow linearly with the text.
+//
+//     cargo bench --bench parser
+use std::hint::black_box;
+use std::io::{BufReader, Cursor};
+use std::time::{Duration, Instant};
+use wikitext_table_parser::parser::WikitextTableParser;
+use wikitext_table_parser::tokenizer::{
+    get_all_cell_text_special_tokens, get_all_table_special_tokens, Tokenizer,
+};
+
+const ROUNDS: u32 = 5;
+
+fn time<F: FnMut() -> usize>(mut f: F) -> (Duration, usize) {
+    // warm up
+    let count = f();
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        black_box(f());
+    }
+    (start.elapsed() / ROUNDS, count)
+}
+
+fn parse(content: &str) -> usize {
+    WikitextTableParser::new(
+        Tokenizer::build(get_all_table_special_tokens()),
+        Tokenizer::build(get_all_cell_text_special_tokens()),
+        content,
+        true,
+    )
+    .count()
+}
+
+fn parse_from_reader(content: &str) -> usize {
+    let reader = BufReader::with_capacity(4096, Cursor::new(content.to_string()));
+    WikitextTableParser::from_reader(
+        Tokenizer::build(get_all_table_special_tokens()),
+        Tokenizer::build(get_all_cell_text_special_tokens()),
+        reader,
+        true,
+    )
+    .count()
+}
+
+fn main() {
+    let table = "\n{|\n| a || b\n|}\n";
+    for (name, before) in [
+        ("text", "some text, a [[link|name]] and <b>bold</b> text - ok!\n"),
+        ("no token", "text without a char which starts a token "),
+        ("<!--", "a line after an unclosed comment | x\n"),
+        ("<nowiki>", "a line after an unclosed nowiki | x\n"),
+    ] {
+        for n in [40_000, 320_000] {
+            let open = if name.starts_with('<') { name } else { "" };
+            let content = table.to_string() + open + &before.repeat(n);
+            let (string, count) = time(|| parse(black_box(&content)));
+            let (reader, reader_count) = time(|| parse_from_reader(black_box(&content)));
+            assert_eq!(count, reader_count);
+            println!(
+                "{:<10} {:>8} bytes  string: {:>10.2?}  reader: {:>10.2?} ({} events)",
+                name,
+                content.len(),
+                string,
+                reader,
+                count
+            );
+        }
+    }
+
+    for tag in ["<pre>a ", "<NOWIKI>a ", "<pre a "] {
+        for n in [2000, 16_000] {
+            let content = format!("{{|\n| {}\n|}}", tag.repeat(n));
+            let (string, count) = time(|| parse(black_box(&content)));
+            println!(
+                "{:<10} {:>8} bytes  string: {:>10.2?} ({} events)",
+                tag,
+                content.len(),
+                string,
+                count
+            );
+        }
+    }
+}
@@ -0,0 +1,131 @@
+// Compare the old `HashMap` trie of `Tokenizer::tokenize` (a `String` per
+// token) with the zero-copy `Tokenizer::tokens` on the tables of
+// `wikitext_tables/`.
+//
+//     cargo bench --bench tokenizer
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use wikitext_table_parser::tokenizer::{
+    get_all_cell_text_special_tokens, get_all_table_special_tokens, Tokenizer,
+};
+
+// The tokenizer before `Tokenizer::tokens`, kept here to compare with.
+mod old {
+    use std::collections::HashMap;
+
+    #[derive(Debug, Clone)]
+    struct TokenParseTreeNode {
+        val: char,
+        children: HashMap<char, TokenParseTreeNode>,
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct Tokenizer {
+        token_tree: TokenParseTreeNode,
+    }
+
+    impl Tokenizer {
+        pub fn build(special_tokens: Vec<String>) -> Self {
+            let mut root_node = TokenParseTreeNode {
+                val: '$', // a root's val is unused
+                children: HashMap::new(),
+            };
+            for token in special_tokens {
+                let mut node = &mut root_node;
+                for t_char in token.chars() {
+                    node = node
+                        .children
+                        .entry(t_char)
+                        .or_insert_with(|| TokenParseTreeNode {
+                            val: t_char,
+                            children: HashMap::new(),
+                        });
+                }
+            }
+            Tokenizer {
+                token_tree: root_node,
+            }
+        }
+
+        pub fn tokenize(&self, raw_str: &str) -> Vec<String> {
+            let mut out: Vec<String> = Vec::new();
+            let mut node = &self.token_tree;
+            let mut tmp = String::new();
+            for t_char in raw_str.chars() {
+                match node.children.get(&t_char) {
+                    Some(child) => {
+                        node = child;
+                        tmp.push(node.val);
+                    }
+                    None => {
+                        if !tmp.is_empty() {
+                            out.push(tmp.clone());
+                        }
+                        // start from head for next loop
+                        match self.token_tree.children.get(&t_char) {
+                            Some(child) => {
+                                tmp = t_char.to_string();
+                                node = child;
+                            }
+                            None => {
+                                tmp = String::new();
+                                node = &self.token_tree;
+                                out.push(t_char.to_string());
+                            }
+                        }
+                    }
+                }
+            }
+            if !tmp.is_empty() {
+                out.push(tmp);
+            }
+            out
+        }
+    }
+}
+
+const ROUNDS: u32 = 20;
+
+fn time<F: FnMut() -> usize>(mut f: F) -> (Duration, usize) {
+    // warm up
+    let count = f();
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        black_box(f());
+    }
+    (start.elapsed() / ROUNDS, count)
+}
+
+fn main() {
+    let mut paths: Vec<_> = std::fs::read_dir("wikitext_tables")
+        .expect("run from the root of the repository")
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "txt"))
+        .collect();
+    paths.sort();
+    let corpus: String = paths
+        .iter()
+        .map(|path| std::fs::read_to_string(path).unwrap())
+        .collect::<Vec<_>>()
+        .join("\n");
+    println!("corpus: {} files, {} bytes", paths.len(), corpus.len());
+
+    for (name, special_tokens) in [
+        ("table", get_all_table_special_tokens()),
+        ("cell", get_all_cell_text_special_tokens()),
+    ] {
+        let old_tokenizer = old::Tokenizer::build(special_tokens.clone());
+        let tokenizer = Tokenizer::build(special_tokens);
+        let (old, old_count) = time(|| old_tokenizer.tokenize(black_box(&corpus)).len());
+        let (new, new_count) = time(|| tokenizer.tokens(black_box(&corpus)).count());
+        println!(
+            "{:<6} old tokenize: {:>10.2?} ({} tokens)  tokens: {:>10.2?} ({} tokens)  {:.1}x",
+            name,
+            old,
+            old_count,
+            new,
+            new_count,
+            old.as_secs_f64() / new.as_secs_f64()
+        );
+    }
+}
@@ -20,6 +20,8 @@ print(parser.tokens)
 
 while (len(parser.tokens) > 0):
     parser.step()
+# close the tables left open at the end of the text
+parser.finish()
 
 for event in parser.event_log_queue:
     if isinstance(event, Event.TableStart):
@@ -0,0 +1,186 @@
+// Parse the attributes of a table, row or cell, e.g. the `TableStyle`
+// `class="wikitable" style="font-size: small;"` or the `ColStyle` `align=left|`.
+// https://www.mediawiki.org/wiki/Help:Tables#Attributes
+
+/// Attributes in the order they first appear, a later duplicate key
+/// overwrites the value (same as MediaWiki). Keys are lowercase.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Attributes {
+    entries: Vec<(String, String)>,
+}
+
+/// The declarations of a CSS `style` attribute, properties are lowercase.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Style {
+    declarations: Vec<(String, String)>,
+}
+
+fn insert(entries: &mut Vec<(String, String)>, key: String, value: String) {
+    match entries.iter_mut().find(|(k, _)| *k == key) {
+        Some(entry) => entry.1 = value,
+        None => entries.push((key, value)),
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    !c.is_whitespace() && !matches!(c, '=' | '"' | '\'' | '|' | '/' | '>' | '<')
+}
+
+pub fn parse_attributes(text: &str) -> Attributes {
+    let chars: Vec<char> = text.chars().collect();
+    let mut entries = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        // skip spaces and stray characters like the `|` ending a cell style
+        if !is_name_char(chars[i]) {
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        while i < chars.len() && is_name_char(chars[i]) {
+            i += 1;
+        }
+        let key: String = chars[start..i].iter().collect::<String>().to_lowercase();
+
+        let mut j = i;
+        while j < chars.len() && chars[j].is_whitespace() {
+            j += 1;
+        }
+        if j >= chars.len() || chars[j] != '=' {
+            // a bare flag, e.g. `hidden`
+            insert(&mut entries, key, String::new());
+            continue;
+        }
+        j += 1;
+        while j < chars.len() && chars[j].is_whitespace() {
+            j += 1;
+        }
+
+        let value: String;
+        if j < chars.len() && (chars[j] == '"' || chars[j] == '\'') {
+            let quote = chars[j];
+            let value_start = j + 1;
+            let mut value_end = value_start;
+            while value_end < chars.len() && chars[value_end] != quote {
+                value_end += 1;
+            }
+            value = chars[value_start..value_end].iter().collect();
+            i = value_end + 1;
+        } else {
+            let value_start = j;
+            let mut value_end = value_start;
+            while value_end < chars.len()
+                && !chars[value_end].is_whitespace()
+                && chars[value_end] != '|'
+            {
+                value_end += 1;
+            }
+            value = chars[value_start..value_end].iter().collect();
+            i = value_end;
+        }
+        insert(&mut entries, key, value);
+    }
+
+    Attributes { entries }
+}
+
+pub fn parse_style(text: &str) -> Style {
+    let mut declarations = Vec::new();
+    let mut declaration = String::new();
+    let mut quote: Option<char> = None;
+    let mut parens = 0;
+
+    // split by `;`, but not the ones in `url(...)` or in a quoted string
+    let mut push = |declaration: &str| {
+        if let Some((property, value)) = declaration.split_once(':') {
+            let property = property.trim().to_lowercase();
+            let value = value.trim();
+            if !property.is_empty() {
+                insert(&mut declarations, property, value.to_string());
+            }
+        }
+    };
+    for c in text.chars() {
+        match (quote, c) {
+            (Some(q), c) if q == c => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '(') => parens += 1,
+            (None, ')') => parens -= 1,
+            (None, ';') if parens <= 0 => {
+                push(&declaration);
+                declaration.clear();
+                continue;
+            }
+            _ => {}
+        }
+        declaration.push(c);
+    }
+    push(&declaration);
+
+    Style { declarations }
+}
+
+impl Attributes {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        let key = key.to_lowercase();
+        self.entries
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The parsed `style` attribute, empty if there is none.
+    pub fn style(&self) -> Style {
+        parse_style(self.get("style").unwrap_or(""))
+    }
+
+    /// A `rowspan` or `colspan` like number, parsed from its leading digits.
+    pub fn get_number(&self, key: &str) -> Option<usize> {
+        let value = self.get(key)?.trim();
+        let digits: String = value.chars().take_while(|c| c.is_ascii_digit()).collect();
+        digits.parse().ok()
+    }
+}
+
+impl Style {
+    pub fn get(&self, property: &str) -> Option<&str> {
+        let property = property.to_lowercase();
+        self.declarations
+            .iter()
+            .find(|(p, _)| *p == property)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.declarations
+            .iter()
+            .map(|(p, v)| (p.as_str(), v.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.declarations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.declarations.is_empty()
+    }
+}
@@ -0,0 +1,228 @@
+use crate::diagnostics::ParseError;
+use crate::document::parse_article_tables;
+use crate::table::Table;
+use std::fmt;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::time::{Duration, Instant};
+
+// Parse many documents, e.g. the pages of a dump. With the `parallel`
+// feature the documents are parsed on a rayon thread pool, otherwise one by one.
+
+/// A text to parse, with an id to tell the results apart.
+#[derive(Debug, Clone, Default)]
+pub struct Document {
+    pub id: u64,
+    pub title: String,
+    pub text: String,
+    // the namespace and the revision of a page of a dump
+    pub namespace: Option<i64>,
+    pub revision_id: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub enum BatchError {
+    // the parser panicked on the document
+    Panic(String),
+    // the document has diagnostics, only in the strict mode
+    Strict(ParseError),
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BatchError::Panic(message) => write!(f, "the parser panicked: {}", message),
+            BatchError::Strict(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for BatchError {}
+
+/// The tables of a document, or why it failed.
+#[derive(Debug, Clone)]
+pub struct BatchOutput {
+    pub id: u64,
+    pub title: String,
+    // same as the ones of the `Document`
+    pub namespace: Option<i64>,
+    pub revision_id: Option<u64>,
+    pub result: Result<Vec<Table>, BatchError>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BatchOptions {
+    /// Return the outputs in the order of the documents, otherwise in the
+    /// order they are done.
+    pub ordered: bool,
+    /// Fail a document which has any diagnostic, see `parse_strict`.
+    pub strict: bool,
+    /// The number of threads, the number of cores if None. Only used with
+    /// the `parallel` feature.
+    pub threads: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BatchStats {
+    pub documents: usize,
+    pub failures: usize,
+    pub tables: usize,
+    // the size of the texts
+    pub bytes: usize,
+    pub elapsed: Duration,
+}
+
+impl BatchStats {
+    fn add(&mut self, bytes: usize, output: &BatchOutput) {
+        self.documents += 1;
+        self.bytes += bytes;
+        match &output.result {
+            Ok(tables) => self.tables += tables.len(),
+            Err(_) => self.failures += 1,
+        }
+    }
+
+    pub fn documents_per_second(&self) -> f64 {
+        self.documents as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    pub fn bytes_per_second(&self) -> f64 {
+        self.bytes as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+impl fmt::Display for BatchStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} documents ({} failed), {} tables in {:.2?}: {:.1} documents/s, {:.2} MB/s",
+            self.documents,
+            self.failures,
+            self.tables,
+            self.elapsed,
+            self.documents_per_second(),
+            self.bytes_per_second() / 1_000_000.0
+        )
+    }
+}
+
+/// Parse the tables of one document found by `scan_tables`, a panic is
+/// caught as a failure.
+pub fn parse_document(document: Document, strict: bool) -> BatchOutput {
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        let (tables, diagnostics) = parse_article_tables(&document.text);
+        if strict && !diagnostics.is_empty() {
+            Err(ParseError { diagnostics })
+        } else {
+            Ok(tables)
+        }
+    }));
+    let result = match result {
+        Ok(Ok(tables)) => Ok(tables),
+        Ok(Err(error)) => Err(BatchError::Strict(error)),
+        Err(panic) => {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            Err(BatchError::Panic(message))
+        }
+    };
+    BatchOutput {
+        id: document.id,
+        title: document.title,
+        namespace: document.namespace,
+        revision_id: document.revision_id,
+        result,
+    }
+}
+
+/// Parse the documents, `on_output` is called with the tables of each
+/// document on the calling thread. The documents are read as they are
+/// parsed, so they could come from a dump larger than the memory.
+#[cfg(not(feature = "parallel"))]
+pub fn parse_batch<I, F>(documents: I, options: &BatchOptions, mut on_output: F) -> BatchStats
+where
+    I: IntoIterator<Item = Document>,
+    I::IntoIter: Send,
+    F: FnMut(BatchOutput),
+{
+    let start = Instant::now();
+    let mut stats = BatchStats::default();
+    for document in documents {
+        let bytes = document.text.len();
+        let output = parse_document(document, options.strict);
+        stats.add(bytes, &output);
+        on_output(output);
+    }
+    stats.elapsed = start.elapsed();
+    stats
+}
+
+/// Parse the documents, `on_output` is called with the tables of each
+/// document on the calling thread. The documents are read as they are
+/// parsed, so they could come from a dump larger than the memory.
+#[cfg(feature = "parallel")]
+pub fn parse_batch<I, F>(documents: I, options: &BatchOptions, mut on_output: F) -> BatchStats
+where
+    I: IntoIterator<Item = Document>,
+    I::IntoIter: Send,
+    F: FnMut(BatchOutput),
+{
+    use rayon::prelude::*;
+    use std::sync::mpsc;
+
+    let start = Instant::now();
+    let mut stats = BatchStats::default();
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(options.threads.unwrap_or(0))
+        .build()
+        .expect("failed to build the thread pool");
+    let strict = options.strict;
+    let mut documents = documents.into_iter();
+
+    if options.ordered {
+        // parse a chunk at a time, to keep the order with bounded memory
+        let chunk_size = pool.current_num_threads() * 16;
+        loop {
+            let chunk: Vec<Document> = documents.by_ref().take(chunk_size).collect();
+            if chunk.is_empty() {
+                break;
+            }
+            let outputs: Vec<(usize, BatchOutput)> = pool.install(|| {
+                chunk
+                    .into_par_iter()
+                    .map(|document| (document.text.len(), parse_document(document, strict)))
+                    .collect()
+            });
+            for (bytes, output) in outputs {
+                stats.add(bytes, &output);
+                on_output(output);
+            }
+        }
+    } else {
+        // the pool sends the outputs back as they are done
+        let (sender, receiver) = mpsc::sync_channel(pool.current_num_threads() * 16);
+        std::thread::scope(|scope| {
+            scope.spawn(move || {
+                pool.install(|| {
+                    documents
+                        .par_bridge()
+                        .for_each_with(sender, |sender, document| {
+                            let bytes = document.text.len();
+                            let output = parse_document(document, strict);
+                            // the receiver is only dropped once all are sent
+                            let _ = sender.send((bytes, output));
+                        })
+                })
+            });
+            for (bytes, output) in receiver {
+                stats.add(bytes, &output);
+                on_output(output);
+            }
+        });
+    }
+
+    stats.elapsed = start.elapsed();
+    stats
+}
@@ -0,0 +1,609 @@
+use crate::parser::{CellType, Event, Span, WikitextTableParser};
+use crate::serialize::{escape_markup, has_style_separator};
+use crate::table::{parse_tables, Table};
+use crate::tokenizer::{get_all_cell_text_special_tokens, get_all_table_special_tokens, Tokenizer};
+use std::fmt;
+use std::ops::Range;
+
+// A lossless syntax tree of the tables, for editing a table without
+// reformatting it. Every byte of the text is kept in a node, the separators
+// keep their form (`||` or `\n|`, `!!` or `\n!`) and the cell text keeps
+// its whitespace, so `to_string()` gives back the text as it was parsed,
+// and an edit only changes the node it is made to.
+// The tree is built from the events of `WikitextTableParser`, the tables,
+// rows and cells are the ones of `parse_tables`.
+
+/// A piece of the text, outside of the tables or in a cell.
+#[derive(Debug, Clone)]
+pub enum CstPart {
+    Text(String),
+    Table(CstTable),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Cst {
+    pub parts: Vec<CstPart>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CstTable {
+    // `{|`, with the line break before it
+    pub open: String,
+    // everything up to the caption or the first row
+    pub attrs: String,
+    pub body: Vec<CstTablePart>,
+    // `|}` with the line break before it, empty if the table is not closed
+    pub close: String,
+}
+
+/// The caption and the rows in the order they are in the table, a caption
+/// may be after a row.
+#[derive(Debug, Clone)]
+pub enum CstTablePart {
+    Caption(CstCaption),
+    Row(CstRow),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CstCaption {
+    // `|+`, with the line break before it
+    pub separator: String,
+    // up to and including the `|` which ends the attributes
+    pub attrs: Option<String>,
+    // the text, and the nested tables
+    pub content: Vec<CstPart>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CstRow {
+    // `|-` with the line break before it, empty for a first row without it
+    pub open: String,
+    pub attrs: String,
+    // the nested tables before the first cell, and the text after them
+    pub nested: Vec<CstPart>,
+    pub cells: Vec<CstCell>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CstCell {
+    pub kind: CellType,
+    // `\n|`, `||`, `\n!` or `!!`
+    pub separator: String,
+    // up to and including the `|` which ends the attributes
+    pub attrs: Option<String>,
+    // the text, and the nested tables
+    pub content: Vec<CstPart>,
+}
+
+fn push_text(parts: &mut Vec<CstPart>, text: &str) {
+    match parts.last_mut() {
+        Some(CstPart::Text(last)) => last.push_str(text),
+        _ => parts.push(CstPart::Text(text.to_string())),
+    }
+}
+
+// the whitespace around the text
+fn outer_whitespace(text: &str) -> (&str, &str) {
+    let end = text.trim_end().len();
+    let start = end - text[..end].trim_start().len();
+    (&text[..start], &text[end..])
+}
+
+// the new text with the whitespace around the old one
+fn replace_keeping_whitespace(old: &str, new: &str, default_lead: &str) -> String {
+    let (lead, trail) = outer_whitespace(old);
+    let lead = if old.trim().is_empty() && lead.is_empty() {
+        default_lead
+    } else {
+        lead
+    };
+    format!("{}{}{}", lead, new, trail)
+}
+
+// the attributes ending with `|`, the whitespace around them kept
+fn replace_attrs(old: Option<&str>, new: &str) -> String {
+    match old.and_then(|old| old.strip_suffix('|')) {
+        Some(attrs) => format!("{}|", replace_keeping_whitespace(attrs, new, " ")),
+        None => format!(" {} |", new),
+    }
+}
+
+// The text and the nested tables replaced by the text. The table markup
+// of the text is written in `<nowiki>`, as by `serialize`, so it stays the
+// text of the cell, and a `|` of the text gets an empty style before it.
+fn set_content(content: &mut Vec<CstPart>, attrs: &mut Option<String>, text: &str) {
+    let text = escape_markup(text);
+    if attrs.is_none() && has_style_separator(text.trim()) {
+        *attrs = Some(" |".to_string());
+    }
+    let old: String = content.iter().map(|part| part.to_string()).collect();
+    *content = vec![CstPart::Text(replace_keeping_whitespace(&old, &text, " "))];
+}
+
+// the tables in the parts
+fn tables_mut(parts: &mut [CstPart]) -> impl Iterator<Item = &mut CstTable> {
+    parts.iter_mut().filter_map(|part| match part {
+        CstPart::Table(table) => Some(table),
+        CstPart::Text(_) => None,
+    })
+}
+
+impl CstCell {
+    /// Replace the text and the nested tables, the whitespace around the
+    /// text is kept. The table markup of the text is escaped.
+    pub fn set_text(&mut self, text: &str) {
+        set_content(&mut self.content, &mut self.attrs, text);
+    }
+
+    /// Replace the attributes, or remove them if None.
+    pub fn set_attrs(&mut self, attrs: Option<&str>) {
+        self.attrs = attrs.map(|attrs| replace_attrs(self.attrs.as_deref(), attrs));
+    }
+}
+
+impl CstCaption {
+    /// Replace the text and the nested tables, the whitespace around the
+    /// text is kept. The table markup of the text is escaped.
+    pub fn set_text(&mut self, text: &str) {
+        set_content(&mut self.content, &mut self.attrs, text);
+    }
+
+    pub fn set_attrs(&mut self, attrs: Option<&str>) {
+        self.attrs = attrs.map(|attrs| replace_attrs(self.attrs.as_deref(), attrs));
+    }
+}
+
+impl CstRow {
+    pub fn set_attrs(&mut self, attrs: &str) {
+        self.attrs = replace_keeping_whitespace(&self.attrs, attrs, " ");
+    }
+}
+
+impl CstTable {
+    pub fn set_attrs(&mut self, attrs: &str) {
+        self.attrs = replace_keeping_whitespace(&self.attrs, attrs, " ");
+    }
+
+    pub fn caption(&self) -> Option<&CstCaption> {
+        self.body.iter().find_map(|part| match part {
+            CstTablePart::Caption(caption) => Some(caption),
+            CstTablePart::Row(_) => None,
+        })
+    }
+
+    pub fn caption_mut(&mut self) -> Option<&mut CstCaption> {
+        self.body.iter_mut().find_map(|part| match part {
+            CstTablePart::Caption(caption) => Some(caption),
+            CstTablePart::Row(_) => None,
+        })
+    }
+
+    // The parts which are rows of `Table::rows`: a row without cells right
+    // before a caption or the end of the table is not one.
+    fn kept_rows(&self) -> Vec<bool> {
+        let mut kept = vec![false; self.body.len()];
+        let mut rows: Vec<usize> = Vec::new();
+        let pop_empty = |rows: &mut Vec<usize>| {
+            if let Some(&i) = rows.last() {
+                if matches!(&self.body[i], CstTablePart::Row(row) if row.cells.is_empty()) {
+                    rows.pop();
+                }
+            }
+        };
+        for (i, part) in self.body.iter().enumerate() {
+            match part {
+                CstTablePart::Row(_) => rows.push(i),
+                CstTablePart::Caption(_) => pop_empty(&mut rows),
+            }
+        }
+        pop_empty(&mut rows);
+        for i in rows {
+            kept[i] = true;
+        }
+        kept
+    }
+
+    /// The rows of the table, the same as `Table::rows`.
+    pub fn rows(&self) -> impl Iterator<Item = &CstRow> {
+        let kept = self.kept_rows();
+        self.body
+            .iter()
+            .zip(kept)
+            .filter_map(|(part, kept)| match part {
+                CstTablePart::Row(row) if kept => Some(row),
+                _ => None,
+            })
+    }
+
+    pub fn rows_mut(&mut self) -> impl Iterator<Item = &mut CstRow> {
+        let kept = self.kept_rows();
+        self.body
+            .iter_mut()
+            .zip(kept)
+            .filter_map(|(part, kept)| match part {
+                CstTablePart::Row(row) if kept => Some(row),
+                _ => None,
+            })
+    }
+
+    /// The cell of `Table::rows[row].cells[cell]`.
+    pub fn cell_mut(&mut self, row: usize, cell: usize) -> Option<&mut CstCell> {
+        self.rows_mut().nth(row)?.cells.get_mut(cell)
+    }
+
+    // the table and its nested tables, in the order they start
+    fn find_table_mut(&mut self, id: usize, next_id: &mut usize) -> Option<&mut CstTable> {
+        if *next_id == id {
+            return Some(self);
+        }
+        *next_id += 1;
+        for part in self.body.iter_mut() {
+            let parts: Vec<&mut Vec<CstPart>> = match part {
+                CstTablePart::Caption(caption) => vec![&mut caption.content],
+                CstTablePart::Row(row) => std::iter::once(&mut row.nested)
+                    .chain(row.cells.iter_mut().map(|cell| &mut cell.content))
+                    .collect(),
+            };
+            for parts in parts {
+                for table in tables_mut(parts) {
+                    if let Some(found) = table.find_table_mut(id, next_id) {
+                        return Some(found);
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+impl Cst {
+    /// The table with the `id` of `Event::TableStart` and `Table`.
+    pub fn table_mut(&mut self, id: usize) -> Option<&mut CstTable> {
+        let mut next_id = 0;
+        for part in self.parts.iter_mut() {
+            if let CstPart::Table(table) = part {
+                if let Some(found) = table.find_table_mut(id, &mut next_id) {
+                    return Some(found);
+                }
+            }
+        }
+        None
+    }
+
+    /// Parse the text of the tree into tables.
+    pub fn to_tables(&self) -> Vec<Table> {
+        parse_tables(&self.to_string())
+    }
+}
+
+impl fmt::Display for CstPart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CstPart::Text(text) => f.write_str(text),
+            CstPart::Table(table) => write!(f, "{}", table),
+        }
+    }
+}
+
+impl fmt::Display for Cst {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for part in &self.parts {
+            write!(f, "{}", part)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for CstTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.open, self.attrs)?;
+        for part in &self.body {
+            match part {
+                CstTablePart::Caption(caption) => write!(f, "{}", caption)?,
+                CstTablePart::Row(row) => write!(f, "{}", row)?,
+            }
+        }
+        f.write_str(&self.close)
+    }
+}
+
+impl fmt::Display for CstCaption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.separator)?;
+        f.write_str(self.attrs.as_deref().unwrap_or_default())?;
+        for part in &self.content {
+            write!(f, "{}", part)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for CstRow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.open, self.attrs)?;
+        for part in &self.nested {
+            write!(f, "{}", part)?;
+        }
+        for cell in &self.cells {
+            write!(f, "{}", cell)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for CstCell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.separator)?;
+        f.write_str(self.attrs.as_deref().unwrap_or_default())?;
+        for part in &self.content {
+            write!(f, "{}", part)?;
+        }
+        Ok(())
+    }
+}
+
+// where the text goes in the open table
+#[derive(Debug, Clone, Copy)]
+enum Target {
+    Attrs,
+    Caption,
+    RowAttrs,
+    Cell,
+}
+
+// An open table, with where the attributes of its caption or cell start.
+struct OpenTable {
+    table: CstTable,
+    target: Target,
+    attrs_start: usize,
+}
+
+struct CstBuilder<'t> {
+    // the text for parse, with the synthetic `\n` of the parser
+    text: &'t str,
+    // the text before it is in the tree
+    cursor: usize,
+    cst: Cst,
+    // the open tables, the last is the innermost
+    stack: Vec<OpenTable>,
+}
+
+impl<'t> CstBuilder<'t> {
+    // The range of the token of the event in the text, with the line break
+    // before it, if it is one of the `tokens` and is not read yet. The span
+    // is shifted back by the synthetic `\n`, and starts after the line break.
+    fn token(&self, span: Span, tokens: &[&str]) -> Option<Range<usize>> {
+        let (start, end) = (span.start + 1, span.end + 1);
+        let token = self.text.get(start..end)?;
+        if start < self.cursor || !tokens.contains(&token) {
+            return None;
+        }
+        match token {
+            "||" | "!!" => Some(start..end),
+            _ => Some(start - 1..end),
+        }
+    }
+
+    // the open parts of the innermost table, where a nested table goes
+    fn parts_mut(open: &mut OpenTable) -> Option<&mut Vec<CstPart>> {
+        match (open.target, open.table.body.last_mut()) {
+            (Target::Caption, Some(CstTablePart::Caption(caption))) => Some(&mut caption.content),
+            (Target::RowAttrs, Some(CstTablePart::Row(row))) => Some(&mut row.nested),
+            (Target::Cell, Some(CstTablePart::Row(row))) => {
+                row.cells.last_mut().map(|cell| &mut cell.content)
+            }
+            _ => None,
+        }
+    }
+
+    // put the text up to `end` in the tree
+    fn flush(&mut self, end: usize) {
+        if end <= self.cursor {
+            return;
+        }
+        let text = &self.text[self.cursor..end];
+        self.cursor = end;
+        let open = match self.stack.last_mut() {
+            Some(open) => open,
+            None => return push_text(&mut self.cst.parts, text),
+        };
+        if let (Target::RowAttrs, Some(CstTablePart::Row(row))) =
+            (open.target, open.table.body.last_mut())
+        {
+            if row.nested.is_empty() {
+                return row.attrs.push_str(text);
+            }
+        }
+        match Self::parts_mut(open) {
+            Some(parts) => push_text(parts, text),
+            None => open.table.attrs.push_str(text),
+        }
+    }
+
+    // split the attributes, which end at `end`, off the text of the caption
+    // or the cell
+    fn split_attrs(&mut self, end: usize) {
+        self.flush(end);
+        let open = match self.stack.last_mut() {
+            Some(open) => open,
+            None => return,
+        };
+        let len = end - open.attrs_start;
+        let parts = match Self::parts_mut(open) {
+            Some(parts) => parts,
+            None => return,
+        };
+        let text = match parts.first_mut() {
+            Some(CstPart::Text(text)) if len <= text.len() => text,
+            _ => return,
+        };
+        let rest = text.split_off(len);
+        let attrs = std::mem::replace(text, rest);
+        if text.is_empty() {
+            parts.remove(0);
+        }
+        match open.table.body.last_mut() {
+            Some(CstTablePart::Caption(caption)) => caption.attrs = Some(attrs),
+            Some(CstTablePart::Row(row)) => {
+                if let Some(cell) = row.cells.last_mut() {
+                    cell.attrs = Some(attrs);
+                }
+            }
+            None => {}
+        }
+    }
+
+    fn open_table(&mut self, range: Range<usize>) {
+        self.flush(range.start);
+        self.cursor = range.end;
+        self.stack.push(OpenTable {
+            table: CstTable {
+                open: self.text[range].to_string(),
+                ..Default::default()
+            },
+            target: Target::Attrs,
+            attrs_start: 0,
+        });
+    }
+
+    fn close_table(&mut self, range: Option<Range<usize>>) {
+        // a table which is not closed ends where the text ends
+        let range = range.unwrap_or(self.text.len()..self.text.len());
+        self.flush(range.start);
+        self.cursor = range.end;
+        let mut table = match self.stack.pop() {
+            Some(open) => open.table,
+            None => return,
+        };
+        table.close = self.text[range].to_string();
+        // a nested table is in the caption, the row or the cell it was started in
+        match self.stack.last_mut().and_then(Self::parts_mut) {
+            Some(parts) => parts.push(CstPart::Table(table)),
+            None => self.cst.parts.push(CstPart::Table(table)),
+        }
+    }
+
+    fn start_caption(&mut self, range: Range<usize>) {
+        self.flush(range.start);
+        self.cursor = range.end;
+        if let Some(open) = self.stack.last_mut() {
+            open.table.body.push(CstTablePart::Caption(CstCaption {
+                separator: self.text[range.clone()].to_string(),
+                ..Default::default()
+            }));
+            open.target = Target::Caption;
+            open.attrs_start = range.end;
+        }
+    }
+
+    // `|-`, or the first cell of a row without it
+    fn start_row(&mut self, range: Range<usize>) {
+        self.flush(range.start);
+        let open = match self.stack.last_mut() {
+            Some(open) => open,
+            None => return,
+        };
+        let token = &self.text[range.clone()];
+        let mut row = CstRow::default();
+        if token.ends_with("|-") {
+            row.open = token.to_string();
+            self.cursor = range.end;
+        }
+        open.table.body.push(CstTablePart::Row(row));
+        open.target = Target::RowAttrs;
+    }
+
+    fn start_cell(&mut self, kind: CellType, range: Range<usize>) {
+        self.flush(range.start);
+        self.cursor = range.end;
+        let open = match self.stack.last_mut() {
+            Some(open) => open,
+            None => return,
+        };
+        if !matches!(open.table.body.last(), Some(CstTablePart::Row(_))) {
+            open.table.body.push(CstTablePart::Row(CstRow::default()));
+        }
+        if let Some(CstTablePart::Row(row)) = open.table.body.last_mut() {
+            row.cells.push(CstCell {
+                kind,
+                separator: self.text[range.clone()].to_string(),
+                attrs: None,
+                content: Vec::new(),
+            });
+        }
+        open.target = Target::Cell;
+        open.attrs_start = range.end;
+    }
+
+    fn step(&mut self, event: Event) {
+        match event {
+            Event::TableStart { span, .. } => {
+                if let Some(range) = self.token(span, &["{|"]) {
+                    self.open_table(range);
+                }
+            }
+            Event::TableCaptionStart { span } => {
+                if let Some(range) = self.token(span, &["|+"]) {
+                    self.start_caption(range);
+                }
+            }
+            Event::RowStart { span } => {
+                if let Some(range) = self.token(span, &["|-", "|", "!"]) {
+                    self.start_row(range);
+                }
+            }
+            Event::ColStart { cell_type, span } => {
+                if let Some(range) = self.token(span, &["|", "||", "!", "!!"]) {
+                    self.start_cell(cell_type, range);
+                }
+            }
+            Event::TableCaptionStyle { text, span } | Event::ColStyle { text, span }
+                if !text.is_empty() =>
+            {
+                self.split_attrs(span.end + 1)
+            }
+            Event::TableEnd { span, .. } => self.close_table(self.token(span, &["|}"])),
+            _ => {}
+        }
+    }
+
+    fn finish(mut self) -> Cst {
+        self.flush(self.text.len());
+        self.cst
+    }
+}
+
+/// Parse the tables of the text into a lossless syntax tree.
+pub fn parse_cst(text: &str) -> Cst {
+    let table_tokenizer = Tokenizer::build(get_all_table_special_tokens());
+    let cell_tokenizer = Tokenizer::build(get_all_cell_text_special_tokens());
+    let parser = WikitextTableParser::new(table_tokenizer, cell_tokenizer, text, false);
+
+    // the same synthetic `\n` as the parser, for a table at the start
+    let text = format!("\n{}", text);
+    let mut builder = CstBuilder {
+        text: &text,
+        cursor: 0,
+        cst: Cst::default(),
+        stack: Vec::new(),
+    };
+    for event in parser {
+        builder.step(event);
+    }
+    let mut cst = builder.finish();
+
+    // remove the synthetic `\n`
+    match cst.parts.first_mut() {
+        Some(CstPart::Text(text)) => {
+            text.remove(0);
+            if text.is_empty() {
+                cst.parts.remove(0);
+            }
+        }
+        Some(CstPart::Table(table)) => {
+            table.open.remove(0);
+        }
+        None => {}
+    }
+    cst
+}
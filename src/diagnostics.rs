@@ -0,0 +1,81 @@
+use crate::parser::Span;
+use pyo3::prelude::*;
+use std::fmt;
+
+// Problems found while parsing a table. The parser never stops on them,
+// each code documents how the parser recovers.
+
+#[pyclass]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[pyclass]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticCode {
+    // `{|` without its `|}`.
+    // Recovery: the table is closed at the end of the text, the open cell,
+    // row or caption is ended first.
+    UnterminatedTable,
+    // `|-` found before any `{|`.
+    // Recovery: ignored, it is not a part of any table.
+    RowOutsideTable,
+    // `|+` found after the first row of cells.
+    // Recovery: the open row is ended and the text is read as the caption.
+    CaptionAfterRow,
+    // `<nowiki>`, `<pre>` or `<!--` without its closing tag.
+    // Recovery: an unclosed comment hides the rest of the text, the others
+    // are kept as text and the table markup after them is parsed.
+    UnclosedMarkup,
+    // The reader of a streaming parse failed.
+    // Recovery: the text read so far is parsed as the whole text.
+    ReadError,
+}
+
+#[pyclass]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    #[pyo3(get)]
+    pub severity: Severity,
+    #[pyo3(get)]
+    pub code: DiagnosticCode,
+    #[pyo3(get)]
+    pub message: String,
+    #[pyo3(get)]
+    pub span: Span,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} {:?} at {}:{}: {}",
+            self.severity, self.code, self.span.line, self.span.column, self.message
+        )
+    }
+}
+
+/// Returned by the strict mode when the table has any diagnostic.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.diagnostics.first() {
+            Some(diagnostic) if self.diagnostics.len() > 1 => write!(
+                f,
+                "{} (and {} more)",
+                diagnostic,
+                self.diagnostics.len() - 1
+            ),
+            Some(diagnostic) => write!(f, "{}", diagnostic),
+            None => write!(f, "parse error"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
@@ -0,0 +1,257 @@
+use crate::diagnostics::Diagnostic;
+use crate::parser::WikitextTableParser;
+use crate::table::{parse_tables, tables_from_events, Table};
+use crate::tokenizer::{get_all_cell_text_special_tokens, get_all_table_special_tokens, Tokenizer};
+use std::ops::Range;
+
+// Find the tables of a whole article. A `{|` only starts a table at the
+// beginning of a line, and not in the regions where wikitext is not parsed.
+// https://www.mediawiki.org/wiki/Help:Tables
+
+// tags whose content is not parsed as wikitext
+const PROTECTED_TAGS: [&str; 5] = ["nowiki", "pre", "syntaxhighlight", "source", "math"];
+
+/// A table found in an article.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocumentTable<'a> {
+    // byte offset of the `{|` in the article
+    pub offset: usize,
+    // the nearest section heading before the table, without the `=`
+    pub heading: Option<String>,
+    // the wikitext from `{|` to its `|}`, including nested tables
+    pub source: &'a str,
+}
+
+impl DocumentTable<'_> {
+    /// Parse the table, followed by its nested tables.
+    pub fn tables(&self) -> Vec<Table> {
+        parse_tables(self.source)
+    }
+}
+
+// the end of the tag starting at `start`, e.g. `<pre class="x">`
+fn tag_end(text: &str, start: usize) -> Option<usize> {
+    text[start..].find('>').map(|i| start + i + 1)
+}
+
+/// Byte ranges of comments, `<nowiki>`, `<pre>`, `<syntaxhighlight>`,
+/// `<source>` and `<math>`, including the tags themselves.
+pub fn protected_regions(text: &str) -> Vec<Range<usize>> {
+    // ascii lowercase keeps the byte offsets
+    let lower = text.to_ascii_lowercase();
+    let mut regions = Vec::new();
+    let mut i = 0;
+    // The tags which are not closed after some offset, and the offset after
+    // which there is no `>`. They are not looked for again after it, which
+    // keeps many unclosed tags linear.
+    let mut unclosed: Vec<&str> = Vec::new();
+    let mut tag_end_missing_from = text.len() + 1;
+
+    while let Some(found) = lower[i..].find('<') {
+        let start = i + found;
+        i = start + 1;
+
+        if lower[start..].starts_with("<!--") {
+            // an unclosed comment hides the rest of the text
+            let end = lower[start + 4..]
+                .find("-->")
+                .map_or(text.len(), |j| start + 4 + j + 3);
+            regions.push(start..end);
+            i = end;
+            continue;
+        }
+
+        for tag in PROTECTED_TAGS {
+            let rest = &lower[start + 1..];
+            let is_tag = rest.starts_with(tag)
+                && rest[tag.len()..]
+                    .starts_with(|c: char| c == '>' || c == '/' || c.is_whitespace());
+            if !is_tag {
+                continue;
+            }
+            if start >= tag_end_missing_from {
+                break;
+            }
+            let open_end = match tag_end(&lower, start) {
+                Some(open_end) => open_end,
+                None => {
+                    tag_end_missing_from = start;
+                    break;
+                }
+            };
+            // `<nowiki/>` has nothing inside
+            if lower[..open_end].ends_with("/>") {
+                regions.push(start..open_end);
+                i = open_end;
+                break;
+            }
+            // an unclosed tag is kept as text
+            if unclosed.contains(&tag) {
+                break;
+            }
+            let close = format!("</{}", tag);
+            match lower[open_end..].find(&close) {
+                Some(j) => {
+                    let end = tag_end(&lower, open_end + j).unwrap_or(text.len());
+                    regions.push(start..end);
+                    i = end;
+                }
+                None => unclosed.push(tag),
+            }
+            break;
+        }
+    }
+
+    regions
+}
+
+/// Remove the comments, and the tags of `<nowiki>` and `<pre>` while
+/// keeping their content.
+pub fn strip_opaque_spans(text: &str) -> String {
+    let mut out = String::new();
+    let mut last = 0;
+    for region in protected_regions(text) {
+        out += &text[last..region.start];
+        last = region.end;
+
+        let markup = &text[region];
+        let lower = markup.to_ascii_lowercase();
+        if lower.starts_with("<!--") {
+            continue;
+        }
+        if lower.starts_with("<nowiki") || lower.starts_with("<pre") {
+            // `<nowiki/>` has no content
+            if let (Some(open_end), Some(close_start)) = (lower.find('>'), lower.rfind("</")) {
+                if open_end < close_start {
+                    out += &markup[open_end + 1..close_start];
+                }
+            }
+            continue;
+        }
+        out += markup;
+    }
+    out += &text[last..];
+    out
+}
+
+fn heading_text(line: &str) -> Option<String> {
+    let line = line.trim_end();
+    let level = line.chars().take_while(|c| *c == '=').count().min(6);
+    if level == 0 || line.len() <= level * 2 || !line.ends_with(&"=".repeat(level)) {
+        return None;
+    }
+    let text = line[level..line.len() - level].trim();
+    if text.is_empty() {
+        None
+    } else {
+        Some(text.to_string())
+    }
+}
+
+/// Find all the top level tables of an article.
+pub fn scan_tables(article: &str) -> Vec<DocumentTable<'_>> {
+    // regions are in order, so are the lines
+    let mut regions = protected_regions(article).into_iter().peekable();
+
+    let mut tables = Vec::new();
+    let mut heading: Option<String> = None;
+    // (offset of `{|`, heading) of the top level table being read
+    let mut open: Option<(usize, Option<String>)> = None;
+    let mut depth = 0;
+    let mut line_start = 0;
+
+    for line in article.split_inclusive('\n') {
+        let offset = line_start;
+        line_start += line.len();
+
+        // as for the parser, a table line is not indented, ` {|` or `:{|` is text
+        while regions.peek().is_some_and(|region| region.end <= offset) {
+            regions.next();
+        }
+        if regions
+            .peek()
+            .is_some_and(|region| region.contains(&offset))
+        {
+            continue;
+        }
+
+        if line.starts_with("{|") {
+            if depth == 0 {
+                open = Some((offset, heading.clone()));
+            }
+            depth += 1;
+        } else if depth > 0 && line.starts_with("|}") {
+            depth -= 1;
+            if depth == 0 {
+                if let Some((start, heading)) = open.take() {
+                    tables.push(DocumentTable {
+                        offset: start,
+                        heading,
+                        source: &article[start..offset + 2],
+                    });
+                }
+            }
+        } else if depth == 0 {
+            if let Some(text) = heading_text(line) {
+                heading = Some(text);
+            }
+        }
+    }
+
+    // a table not closed runs to the end of the article
+    if let Some((start, heading)) = open {
+        tables.push(DocumentTable {
+            offset: start,
+            heading,
+            source: &article[start..],
+        });
+    }
+
+    tables
+}
+
+/// Parse the tables found by `scan_tables`, each followed by its nested
+/// tables. The ids go on from one table of the article to the next, and
+/// the spans of the diagnostics are the ones in the article.
+pub fn parse_article_tables(article: &str) -> (Vec<Table>, Vec<Diagnostic>) {
+    let table_tokenizer = Tokenizer::build(get_all_table_special_tokens());
+    let cell_tokenizer = Tokenizer::build(get_all_cell_text_special_tokens());
+    let mut tables: Vec<Table> = Vec::new();
+    let mut diagnostics = Vec::new();
+    // the offset, the line and the column of the last table
+    let (mut offset, mut line, mut column) = (0, 1, 1);
+    for document_table in scan_tables(article) {
+        for c in article[offset..document_table.offset].chars() {
+            if c == '\n' {
+                line += 1;
+                column = 1;
+            } else {
+                column += 1;
+            }
+        }
+        offset = document_table.offset;
+
+        let mut parser = WikitextTableParser::new(
+            table_tokenizer.clone(),
+            cell_tokenizer.clone(),
+            document_table.source,
+            false,
+        );
+        let base = tables.len();
+        for mut table in tables_from_events(parser.by_ref()) {
+            table.shift_ids(base);
+            tables.push(table);
+        }
+        diagnostics.extend(parser.diagnostics().iter().cloned().map(|mut diagnostic| {
+            let span = &mut diagnostic.span;
+            span.start += offset;
+            span.end += offset;
+            if span.line == 1 {
+                span.column += column - 1;
+            }
+            span.line += line - 1;
+            diagnostic
+        }));
+    }
+    (tables, diagnostics)
+}
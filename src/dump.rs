@@ -0,0 +1,275 @@
+use crate::batch::Document;
+use crate::document::parse_article_tables;
+use crate::table::Table;
+use bzip2::read::MultiBzDecoder;
+use quick_xml::events::Event as XmlEvent;
+use quick_xml::Reader;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+// Read the pages of a MediaWiki XML dump, e.g. `enwiki-latest-pages-articles.xml.bz2`.
+// https://www.mediawiki.org/wiki/Help:Export#Export_format
+
+/// A page of the dump, with the tables of its last revision.
+#[derive(Debug, Clone)]
+pub struct DumpPage {
+    pub page_id: u64,
+    pub title: String,
+    pub namespace: i64,
+    pub revision_id: u64,
+    pub tables: Vec<Table>,
+}
+
+#[derive(Debug)]
+pub enum DumpError {
+    Io(io::Error),
+    // the XML is broken, at the byte position of the decompressed dump
+    Xml {
+        position: u64,
+        error: quick_xml::Error,
+    },
+}
+
+impl fmt::Display for DumpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DumpError::Io(error) => write!(f, "failed to read the dump: {}", error),
+            DumpError::Xml { position, error } => {
+                write!(f, "broken XML at byte {}: {}", position, error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DumpError {}
+
+impl From<io::Error> for DumpError {
+    fn from(error: io::Error) -> Self {
+        DumpError::Io(error)
+    }
+}
+
+// the fields of the page being read
+#[derive(Debug, Default)]
+struct PageFields {
+    title: String,
+    namespace: i64,
+    page_id: u64,
+    revision_id: u64,
+    text: String,
+    is_redirect: bool,
+}
+
+/// An iterator of the pages of a dump.
+pub struct DumpReader<R: BufRead> {
+    reader: Reader<R>,
+    buf: Vec<u8>,
+    done: bool,
+    /// Skip the pages with `<redirect/>`.
+    pub skip_redirects: bool,
+    /// Only read the pages of these namespaces, e.g. `vec![0]` for articles.
+    pub namespaces: Option<Vec<i64>>,
+}
+
+// Open a file which may be compressed, `.bz2` is found by its magic bytes.
+pub(crate) fn open_maybe_bz2<P: AsRef<Path>>(path: P) -> io::Result<Box<dyn BufRead + Send>> {
+    let mut file = BufReader::new(File::open(path)?);
+    if file.fill_buf()?.starts_with(b"BZh") {
+        Ok(Box::new(BufReader::new(MultiBzDecoder::new(file))))
+    } else {
+        Ok(Box::new(file))
+    }
+}
+
+/// Open a dump file, plain or `.bz2`.
+pub fn open_dump<P: AsRef<Path>>(path: P) -> io::Result<DumpReader<Box<dyn BufRead + Send>>> {
+    Ok(DumpReader::new(open_maybe_bz2(path)?))
+}
+
+impl<R: BufRead> DumpReader<R> {
+    pub fn new(reader: R) -> Self {
+        DumpReader {
+            reader: Reader::from_reader(reader),
+            buf: Vec::new(),
+            done: false,
+            skip_redirects: false,
+            namespaces: None,
+        }
+    }
+
+    fn error(&self, error: quick_xml::Error) -> DumpError {
+        match error {
+            quick_xml::Error::Io(error) => DumpError::Io(io::Error::new(error.kind(), error)),
+            error => DumpError::Xml {
+                position: self.reader.buffer_position(),
+                error,
+            },
+        }
+    }
+
+    fn is_wanted(&self, page: &PageFields) -> bool {
+        if self.skip_redirects && page.is_redirect {
+            return false;
+        }
+        match &self.namespaces {
+            Some(namespaces) => namespaces.contains(&page.namespace),
+            None => true,
+        }
+    }
+
+    // Read up to the next `</page>`, None at the end of the dump.
+    fn read_page(&mut self) -> Result<Option<PageFields>, DumpError> {
+        let mut page: Option<PageFields> = None;
+        // the open elements inside of `<page>`
+        let mut path: Vec<Vec<u8>> = Vec::new();
+
+        loop {
+            self.buf.clear();
+            let event = match self.reader.read_event_into(&mut self.buf) {
+                Ok(event) => event.into_owned(),
+                Err(error) => return Err(self.error(error)),
+            };
+            match event {
+                XmlEvent::Start(start) => {
+                    let name = start.local_name().as_ref().to_vec();
+                    if name == b"page" {
+                        page = Some(PageFields::default());
+                        path.clear();
+                    } else if page.is_some() {
+                        // a revision replaces the text of the one before it
+                        if name == b"text" {
+                            if let Some(page) = page.as_mut() {
+                                page.text.clear();
+                            }
+                        }
+                        path.push(name);
+                    }
+                }
+                XmlEvent::Empty(empty) => {
+                    if let Some(page) = page.as_mut() {
+                        match empty.local_name().as_ref() {
+                            b"redirect" if path.is_empty() => page.is_redirect = true,
+                            b"text" => page.text.clear(),
+                            _ => {}
+                        }
+                    }
+                }
+                XmlEvent::End(end) => {
+                    if end.local_name().as_ref() == b"page" {
+                        if page.is_some() {
+                            return Ok(page);
+                        }
+                    } else {
+                        path.pop();
+                    }
+                }
+                XmlEvent::Text(text) => {
+                    if let Some(page) = page.as_mut() {
+                        let text = match text.unescape() {
+                            Ok(text) => text.into_owned(),
+                            Err(error) => return Err(self.error(error)),
+                        };
+                        read_field(page, &path, &text);
+                    }
+                }
+                XmlEvent::CData(data) => {
+                    if let Some(page) = page.as_mut() {
+                        read_field(page, &path, &String::from_utf8_lossy(&data));
+                    }
+                }
+                XmlEvent::Eof => return Ok(None),
+                _ => {}
+            }
+        }
+    }
+
+    // the next page which is not skipped
+    fn next_page(&mut self) -> Option<Result<PageFields, DumpError>> {
+        while !self.done {
+            match self.read_page() {
+                Ok(Some(page)) if self.is_wanted(&page) => return Some(Ok(page)),
+                Ok(Some(_)) => {}
+                Ok(None) => self.done = true,
+                Err(error) => {
+                    // a broken dump is not read any further
+                    self.done = true;
+                    return Some(Err(error));
+                }
+            }
+        }
+        None
+    }
+
+    // the page with the id, the text of the other pages is not parsed
+    pub(crate) fn find_page(&mut self, page_id: u64) -> Result<Option<DumpPage>, DumpError> {
+        while let Some(page) = self.next_page() {
+            let page = page?;
+            if page.page_id == page_id {
+                return Ok(Some(page.into()));
+            }
+        }
+        Ok(None)
+    }
+
+    /// The pages with their text not parsed, e.g. to parse them by
+    /// `batch::parse_batch`.
+    pub fn documents(self) -> DumpDocuments<R> {
+        DumpDocuments { reader: self }
+    }
+}
+
+impl From<PageFields> for DumpPage {
+    fn from(page: PageFields) -> Self {
+        DumpPage {
+            page_id: page.page_id,
+            tables: parse_article_tables(&page.text).0,
+            title: page.title,
+            namespace: page.namespace,
+            revision_id: page.revision_id,
+        }
+    }
+}
+
+fn read_field(page: &mut PageFields, path: &[Vec<u8>], text: &str) {
+    let path: Vec<&[u8]> = path.iter().map(|name| name.as_slice()).collect();
+    match path.as_slice() {
+        [b"title"] => page.title += text,
+        [b"ns"] => page.namespace = text.trim().parse().unwrap_or_default(),
+        [b"id"] => page.page_id = text.trim().parse().unwrap_or_default(),
+        [b"revision", b"id"] => page.revision_id = text.trim().parse().unwrap_or_default(),
+        [b"revision", b"text"] => page.text += text,
+        _ => {}
+    }
+}
+
+impl<R: BufRead> Iterator for DumpReader<R> {
+    type Item = Result<DumpPage, DumpError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // the text of a skipped page is not parsed
+        let page = self.next_page()?;
+        Some(page.map(DumpPage::from))
+    }
+}
+
+/// The pages of a dump as `Document`, returned by `DumpReader::documents`.
+pub struct DumpDocuments<R: BufRead> {
+    reader: DumpReader<R>,
+}
+
+impl<R: BufRead> Iterator for DumpDocuments<R> {
+    type Item = Result<Document, DumpError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let page = self.reader.next_page()?;
+        Some(page.map(|page| Document {
+            id: page.page_id,
+            title: page.title,
+            text: page.text,
+            namespace: Some(page.namespace),
+            revision_id: Some(page.revision_id),
+        }))
+    }
+}
@@ -0,0 +1,193 @@
+use crate::grid::Grid;
+use crate::parser::{CellType, Event};
+use crate::table::{tables_from_events, Table};
+use std::io::{self, Write};
+
+// Write the tables as CSV (RFC 4180) or TSV, one line for each row of the
+// grid of the table.
+// https://www.rfc-editor.org/rfc/rfc4180
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum CsvFormat {
+    // fields with `,`, `"` or a line break are quoted, lines end with CRLF
+    #[default]
+    Csv,
+    // a tab or a line break in a field is replaced by a space, lines end with LF
+    Tsv,
+}
+
+/// What to do with the header rows, the rows at the top of the table which
+/// only have header cells (`!`).
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum HeaderRows {
+    // written as the other rows
+    #[default]
+    Keep,
+    // joined into one row, e.g. a `colspan` header above its sub headers
+    Merge,
+    // not written
+    Skip,
+}
+
+/// How to fill the slots covered by the `rowspan`/`colspan` of a cell.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SpanFill {
+    // the text of the cell in every slot it covers
+    #[default]
+    Repeat,
+    // the text in the top left slot, the other slots are empty
+    Empty,
+}
+
+/// How the text of a cell is cleaned before it is written.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum CellCleaning {
+    // the text as it is in the wikitext
+    Raw,
+    // leading and trailing whitespace removed
+    #[default]
+    Trim,
+    // trimmed, and any run of whitespace or line breaks made one space
+    Collapse,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct CsvOptions {
+    pub format: CsvFormat,
+    pub header_rows: HeaderRows,
+    pub span_fill: SpanFill,
+    pub cleaning: CellCleaning,
+}
+
+impl CellCleaning {
+    pub fn clean(&self, text: &str) -> String {
+        match self {
+            CellCleaning::Raw => text.to_string(),
+            CellCleaning::Trim => text.trim().to_string(),
+            CellCleaning::Collapse => text.split_whitespace().collect::<Vec<_>>().join(" "),
+        }
+    }
+}
+
+impl CsvFormat {
+    fn write_field<W: Write>(&self, writer: &mut W, field: &str) -> io::Result<()> {
+        match self {
+            CsvFormat::Csv => {
+                if field.contains([',', '"', '\r', '\n']) {
+                    write!(writer, "\"{}\"", field.replace('"', "\"\""))
+                } else {
+                    writer.write_all(field.as_bytes())
+                }
+            }
+            CsvFormat::Tsv => {
+                let field = field.replace("\r\n", " ").replace(['\t', '\r', '\n'], " ");
+                writer.write_all(field.as_bytes())
+            }
+        }
+    }
+
+    fn write_record<W: Write>(&self, writer: &mut W, record: &[String]) -> io::Result<()> {
+        let (separator, terminator) = match self {
+            CsvFormat::Csv => (",", "\r\n"),
+            CsvFormat::Tsv => ("\t", "\n"),
+        };
+        for (i, field) in record.iter().enumerate() {
+            if i > 0 {
+                writer.write_all(separator.as_bytes())?;
+            }
+            self.write_field(writer, field)?;
+        }
+        writer.write_all(terminator.as_bytes())
+    }
+}
+
+/// The rows of the table as records of the same length, with the spans
+/// filled and the header rows handled by the options.
+pub fn table_records(table: &Table, options: &CsvOptions) -> Vec<Vec<String>> {
+    let grid = Grid::from_table(table);
+    let width = grid.slots.first().map_or(0, |row| row.len());
+    let text = |row: usize, col: usize, fill: SpanFill| -> String {
+        match grid.get(row, col) {
+            Some(slot) if slot.is_origin || fill == SpanFill::Repeat => options
+                .cleaning
+                .clean(&table.rows[slot.row].cells[slot.cell].text),
+            _ => String::new(),
+        }
+    };
+
+    let header_count = (0..grid.height())
+        .take_while(|&row| {
+            (0..width).all(|col| {
+                grid.cell(row, col)
+                    .is_none_or(|cell| matches!(cell.kind, CellType::HeaderCell))
+            })
+        })
+        .count();
+
+    let mut records = Vec::new();
+    if options.header_rows == HeaderRows::Merge && header_count > 0 {
+        let record = (0..width)
+            .map(|col| {
+                // the text of a cell spanning the header rows is taken once
+                let mut texts: Vec<String> = Vec::new();
+                for row in 0..header_count {
+                    let text = text(row, col, SpanFill::Repeat);
+                    if !text.is_empty() && texts.last() != Some(&text) {
+                        texts.push(text);
+                    }
+                }
+                texts.join(" ")
+            })
+            .collect();
+        records.push(record);
+    }
+    let first_row = match options.header_rows {
+        HeaderRows::Keep => 0,
+        HeaderRows::Merge | HeaderRows::Skip => header_count,
+    };
+    for row in first_row..grid.height() {
+        records.push(
+            (0..width)
+                .map(|col| text(row, col, options.span_fill))
+                .collect(),
+        );
+    }
+    records
+}
+
+/// Write the table, one line for each record of `table_records`.
+pub fn write_table<W: Write>(
+    writer: &mut W,
+    table: &Table,
+    options: &CsvOptions,
+) -> io::Result<()> {
+    for record in table_records(table, options) {
+        options.format.write_record(writer, &record)?;
+    }
+    Ok(())
+}
+
+/// The table as a CSV or TSV string.
+pub fn table_to_csv(table: &Table, options: &CsvOptions) -> String {
+    let mut buf = Vec::new();
+    write_table(&mut buf, table, options).expect("writing to a Vec does not fail");
+    String::from_utf8(buf).expect("the fields are strings")
+}
+
+/// Write every table of the events, including the nested tables, in the
+/// order they start. The tables are separated by an empty line. Returns the
+/// number of tables written.
+pub fn write_events<W, I>(writer: &mut W, events: I, options: &CsvOptions) -> io::Result<usize>
+where
+    W: Write,
+    I: IntoIterator<Item = Event>,
+{
+    let tables = tables_from_events(events);
+    for (i, table) in tables.iter().enumerate() {
+        if i > 0 {
+            options.format.write_record(writer, &[])?;
+        }
+        write_table(writer, table, options)?;
+    }
+    Ok(tables.len())
+}
@@ -0,0 +1,163 @@
+use crate::table::{Cell, Table};
+
+// Resolve `rowspan` and `colspan` of a table into a rectangular grid.
+// https://html.spec.whatwg.org/multipage/tables.html#forming-a-table
+
+// the largest spans, as MediaWiki caps them
+// https://www.mediawiki.org/wiki/Help:Tables#Colspan_and_rowspan
+const MAX_COLSPAN: usize = 1000;
+const MAX_ROWSPAN: usize = 65534;
+
+/// A (row, col) position of the grid, covered by the cell
+/// `table.rows[row].cells[cell]`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Slot {
+    pub row: usize,
+    pub cell: usize,
+    // the top left slot of a spanned cell
+    pub is_origin: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GridIssueKind {
+    // the row covers more columns than the first row declares,
+    // or a rowspan goes beyond the last row
+    Overflow,
+    // the row covers less columns than the first row declares
+    Underflow,
+    // a cell spans over the slots of the row taken by another cell, they are
+    // kept by the other cell and the cell covers `actual` of its `expected`
+    Conflict,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GridIssue {
+    pub kind: GridIssueKind,
+    pub row: usize,
+    pub expected: usize,
+    pub actual: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Grid<'a> {
+    table: &'a Table,
+    // declared width, the number of columns covered by the first row
+    pub width: usize,
+    pub slots: Vec<Vec<Option<Slot>>>,
+    pub issues: Vec<GridIssue>,
+}
+
+impl<'a> Grid<'a> {
+    pub fn from_table(table: &'a Table) -> Self {
+        let height = table.rows.len();
+        let mut slots: Vec<Vec<Option<Slot>>> = vec![Vec::new(); height];
+        let mut issues = Vec::new();
+
+        for (row_idx, row) in table.rows.iter().enumerate() {
+            let mut col = 0;
+            for (cell_idx, cell) in row.cells.iter().enumerate() {
+                // skip the slots taken by rowspan of the rows above
+                while slots[row_idx].get(col).is_some_and(|slot| slot.is_some()) {
+                    col += 1;
+                }
+                let attributes = cell.attributes();
+                let colspan = attributes
+                    .get_number("colspan")
+                    .unwrap_or(1)
+                    .clamp(1, MAX_COLSPAN);
+                // `rowspan="0"` spans to the end of the table
+                let rowspan = match attributes.get_number("rowspan") {
+                    Some(0) => height - row_idx,
+                    Some(n) => n.min(MAX_ROWSPAN),
+                    None => 1,
+                };
+                if row_idx.saturating_add(rowspan) > height {
+                    issues.push(GridIssue {
+                        kind: GridIssueKind::Overflow,
+                        row: row_idx,
+                        expected: height - row_idx,
+                        actual: rowspan,
+                    });
+                }
+                for (r, slot_row) in slots.iter_mut().enumerate().skip(row_idx).take(rowspan) {
+                    let end = col.saturating_add(colspan);
+                    if slot_row.len() < end {
+                        slot_row.resize(end, None);
+                    }
+                    let mut covered = 0;
+                    for (c, slot) in slot_row.iter_mut().enumerate().take(end).skip(col) {
+                        if slot.is_some() {
+                            continue;
+                        }
+                        *slot = Some(Slot {
+                            row: row_idx,
+                            cell: cell_idx,
+                            is_origin: r == row_idx && c == col,
+                        });
+                        covered += 1;
+                    }
+                    if covered < colspan {
+                        issues.push(GridIssue {
+                            kind: GridIssueKind::Conflict,
+                            row: r,
+                            expected: colspan,
+                            actual: covered,
+                        });
+                    }
+                }
+                col = col.saturating_add(colspan);
+            }
+        }
+
+        let width = slots.first().map_or(0, |row| row.len());
+        for (row_idx, row) in slots.iter().enumerate() {
+            let actual = row.iter().filter(|slot| slot.is_some()).count();
+            if row.len() > width {
+                issues.push(GridIssue {
+                    kind: GridIssueKind::Overflow,
+                    row: row_idx,
+                    expected: width,
+                    actual: row.len(),
+                });
+            } else if actual < width {
+                issues.push(GridIssue {
+                    kind: GridIssueKind::Underflow,
+                    row: row_idx,
+                    expected: width,
+                    actual,
+                });
+            }
+        }
+
+        // make it rectangular, the missing slots are left as None
+        let max_width = slots.iter().map(|row| row.len()).max().unwrap_or(0);
+        for row in slots.iter_mut() {
+            row.resize(max_width, None);
+        }
+
+        Grid {
+            table,
+            width,
+            slots,
+            issues,
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> Option<Slot> {
+        self.slots
+            .get(row)
+            .and_then(|r| r.get(col))
+            .copied()
+            .flatten()
+    }
+
+    /// The cell that covers the slot.
+    pub fn cell(&self, row: usize, col: usize) -> Option<&'a Cell> {
+        let slot = self.get(row, col)?;
+        self.table.rows[slot.row].cells.get(slot.cell)
+    }
+}
@@ -0,0 +1,304 @@
+use crate::attributes::{parse_attributes, parse_style, Attributes};
+use crate::inline::{
+    link_label, link_target_text, link_text, parse_inline, write_nodes, Inline, InlineWriter,
+};
+use crate::parser::{CellType, Event};
+use crate::table::{tables_from_events, Table};
+
+// Render the tables as HTML, e.g. to preview them. Only the attributes
+// which MediaWiki allows on the table elements are kept, and the style is
+// rebuilt from its declarations without the ones which could run a script.
+// https://www.mediawiki.org/wiki/Help:Sanitizer
+
+#[derive(Debug, Clone)]
+pub struct HtmlOptions {
+    /// Render `[[links]]`, `[https://external links]`, `'''bold'''`,
+    /// `''italic''` and `<br>` of the cell text, otherwise the text is
+    /// escaped as it is.
+    pub render_markup: bool,
+    /// Prepended to the title of an internal link.
+    pub link_prefix: String,
+}
+
+impl Default for HtmlOptions {
+    fn default() -> Self {
+        HtmlOptions {
+            render_markup: false,
+            link_prefix: "/wiki/".to_string(),
+        }
+    }
+}
+
+const COMMON_ATTRS: [&str; 6] = ["class", "id", "style", "title", "lang", "dir"];
+
+// the attributes allowed on each element, besides `COMMON_ATTRS`
+fn element_attrs(element: &str) -> &'static [&'static str] {
+    match element {
+        "table" => &[
+            "align",
+            "bgcolor",
+            "border",
+            "cellpadding",
+            "cellspacing",
+            "frame",
+            "rules",
+            "summary",
+            "width",
+        ],
+        "caption" => &["align"],
+        "tr" => &["align", "bgcolor", "valign"],
+        "td" | "th" => &[
+            "abbr", "align", "axis", "bgcolor", "colspan", "headers", "height", "nowrap",
+            "rowspan", "scope", "valign", "width",
+        ],
+        _ => &[],
+    }
+}
+
+pub fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+// The style without its `/* */` comments, an unclosed one runs to the end.
+// A comment is replaced by a space, so it can not join or split a word
+// which is checked, e.g. `expr/**/ession`.
+fn strip_css_comments(style: &str) -> String {
+    let mut out = String::with_capacity(style.len());
+    let mut rest = style;
+    while let Some(start) = rest.find("/*") {
+        out.push_str(&rest[..start]);
+        out.push(' ');
+        rest = match rest[start + 2..].find("*/") {
+            Some(end) => &rest[start + 2 + end + 2..],
+            None => "",
+        };
+    }
+    out.push_str(rest);
+    out
+}
+
+// a style without the declarations which could load or run anything
+fn sanitize_style(style: &str) -> String {
+    parse_style(&strip_css_comments(style))
+        .iter()
+        .filter(|(property, value)| {
+            let value: String = value
+                .to_ascii_lowercase()
+                .chars()
+                .filter(|c| !c.is_whitespace())
+                .collect();
+            property
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-')
+                && ![
+                    "expression",
+                    "url(",
+                    "image(",
+                    "image-set(",
+                    "-webkit-image-set(",
+                    "var(",
+                    "javascript:",
+                    "behavior",
+                    "-moz-binding",
+                    "\\",
+                ]
+                .iter()
+                .any(|unsafe_value| value.contains(unsafe_value))
+        })
+        .map(|(property, value)| format!("{}: {};", property, value))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The attributes of the text which are allowed on the element, as they
+/// are written in its start tag, e.g. ` class="wikitable" colspan="2"`.
+pub fn sanitize_attributes(element: &str, attributes: &Attributes) -> String {
+    let mut out = String::new();
+    for (key, value) in attributes.iter() {
+        if !COMMON_ATTRS.contains(&key) && !element_attrs(element).contains(&key) {
+            continue;
+        }
+        let value = match key {
+            "style" => sanitize_style(value),
+            "rowspan" | "colspan" => match attributes.get_number(key) {
+                Some(n) => n.to_string(),
+                None => continue,
+            },
+            _ => value.to_string(),
+        };
+        if key == "style" && value.is_empty() {
+            continue;
+        }
+        out += &format!(" {}=\"{}\"", key, escape_html(&value));
+    }
+    out
+}
+
+// the path of a page title, e.g. `/wiki/Noble_gas#Properties`
+pub(crate) fn link_href(prefix: &str, target: &str) -> String {
+    let target = target.trim().trim_start_matches(':').replace(' ', "_");
+    let mut href = prefix.to_string();
+    for c in target.chars() {
+        match c {
+            '"' | '\'' | '<' | '>' | '?' | '&' | '%' => href += &format!("%{:02X}", c as u32),
+            c => href.push(c),
+        }
+    }
+    href
+}
+
+// Renders the links, bold, italic and `<br>` of the nodes, the rest of
+// the wikitext is escaped.
+struct MarkupWriter<'a> {
+    options: &'a HtmlOptions,
+}
+
+impl InlineWriter for MarkupWriter<'_> {
+    fn write_node(&self, out: &mut String, node: &Inline) -> bool {
+        match node {
+            Inline::Link {
+                target,
+                anchor,
+                label,
+            } => {
+                let href = link_href(&self.options.link_prefix, &link_target_text(target, anchor));
+                *out += &format!("<a href=\"{}\">", escape_html(&href));
+                match link_label(label) {
+                    Some(label) => write_nodes(out, label, self),
+                    None => *out += &escape_html(&link_text(target, anchor, label)),
+                }
+                *out += "</a>";
+            }
+            Inline::ExternalLink { url, label } => {
+                *out += &format!("<a class=\"external\" href=\"{}\">", escape_html(url));
+                match label {
+                    Some(label) => write_nodes(out, label, self),
+                    None => *out += &escape_html(url),
+                }
+                *out += "</a>";
+            }
+            Inline::Bold(children) | Inline::Italic(children) => {
+                let tag = match node {
+                    Inline::Bold(_) => "b",
+                    _ => "i",
+                };
+                *out += &format!("<{}>", tag);
+                write_nodes(out, children, self);
+                *out += &format!("</{}>", tag);
+            }
+            Inline::Element { name, .. } if name == "br" => *out += "<br>",
+            _ => return false,
+        }
+        true
+    }
+
+    fn write_text(&self, out: &mut String, text: &str) {
+        *out += &escape_html(text);
+    }
+}
+
+// Render the inline markup of the text: links, bold, italic and `<br>`,
+// the rest is escaped.
+fn render_markup(text: &str, options: &HtmlOptions) -> String {
+    let mut out = String::new();
+    write_nodes(&mut out, &parse_inline(text), &MarkupWriter { options });
+    out
+}
+
+// the text of a cell of `table`, with its nested tables
+fn write_cell_text(
+    out: &mut String,
+    text: &str,
+    table: &Table,
+    tables: &[Table],
+    options: &HtmlOptions,
+) {
+    let render = |text: &str| match options.render_markup {
+        true => render_markup(text, options),
+        false => escape_html(text),
+    };
+    let mut rest = text.trim();
+    // a nested table is rendered in the cell
+    while let Some((id, start, end)) = table.find_child_placeholder(rest) {
+        *out += &render(&rest[..start]);
+        match tables.iter().find(|nested| nested.id == id) {
+            Some(nested) => {
+                out.push('\n');
+                write_table(out, nested, tables, options);
+                out.push('\n');
+            }
+            None => *out += &render(&rest[start..end]),
+        }
+        rest = &rest[end..];
+    }
+    *out += &render(rest);
+}
+
+fn write_table(out: &mut String, table: &Table, tables: &[Table], options: &HtmlOptions) {
+    *out += &format!(
+        "<table{}>\n",
+        sanitize_attributes("table", &table.attributes())
+    );
+    if let Some(caption) = &table.caption {
+        *out += &format!(
+            "<caption{}>",
+            sanitize_attributes("caption", &table.caption_attributes())
+        );
+        write_cell_text(out, caption, table, tables, options);
+        *out += "</caption>\n";
+    }
+    for row in &table.rows {
+        *out += &format!("<tr{}>\n", sanitize_attributes("tr", &row.attributes()));
+        for cell in &row.cells {
+            let element = match cell.kind {
+                CellType::HeaderCell => "th",
+                CellType::DataCell => "td",
+            };
+            *out += &format!(
+                "<{}{}>",
+                element,
+                sanitize_attributes(element, &parse_attributes(&cell.attrs))
+            );
+            write_cell_text(out, &cell.text, table, tables, options);
+            *out += &format!("</{}>\n", element);
+        }
+        *out += "</tr>\n";
+    }
+    *out += "</table>";
+}
+
+/// Render one table, the placeholders of its nested tables are escaped.
+pub fn table_to_html(table: &Table, options: &HtmlOptions) -> String {
+    let mut out = String::new();
+    write_table(&mut out, table, &[], options);
+    out
+}
+
+/// Render the tables as returned by `tables_from_events`, the nested
+/// tables in the cells they were found in.
+pub fn tables_to_html(tables: &[Table], options: &HtmlOptions) -> String {
+    let mut out = String::new();
+    for table in tables.iter().filter(|table| table.depth == 0) {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        write_table(&mut out, table, tables, options);
+    }
+    out
+}
+
+/// Render the tables of the events.
+pub fn events_to_html<I: IntoIterator<Item = Event>>(events: I, options: &HtmlOptions) -> String {
+    tables_to_html(&tables_from_events(events), options)
+}
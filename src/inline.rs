@@ -0,0 +1,890 @@
+use crate::attributes::parse_attributes;
+use crate::document::protected_regions;
+use crate::parser::nested_table_placeholder;
+use crate::tokenizer::{
+    get_all_cell_text_special_tokens, CellTextSpecialTokens, Token, Tokenizer, TypedTokenizer,
+};
+use std::fmt;
+use std::sync::OnceLock;
+
+// Parse the text of a cell into inline nodes, from the tokens of the cell
+// tokenizer. A link, template or external link which is not closed is read
+// as text, while bold, italic and HTML elements are closed at the end of
+// the text, as MediaWiki does.
+// https://www.mediawiki.org/wiki/Help:Formatting
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Inline {
+    Text(String),
+    // `[[target#anchor|label]]`, the label is `None` without a `|`
+    Link {
+        target: String,
+        anchor: Option<String>,
+        label: Option<Vec<Inline>>,
+    },
+    // `[url label]`, or `[url]`
+    ExternalLink {
+        url: String,
+        label: Option<Vec<Inline>>,
+    },
+    // `{{name|positional|key=named}}`
+    Template {
+        name: String,
+        args: Vec<TemplateArg>,
+    },
+    Bold(Vec<Inline>),
+    Italic(Vec<Inline>),
+    // e.g. `<span style="color:red">...</span>` or `<br />`
+    Element {
+        name: String,
+        attrs: String,
+        children: Vec<Inline>,
+    },
+    // `<ref name="a">...</ref>`, or `<ref name="a" />`
+    Reference {
+        name: Option<String>,
+        content: Vec<Inline>,
+    },
+    // `[[File:Example.png|thumb|caption]]`, also `Image:`
+    File {
+        target: String,
+        options: Vec<String>,
+        caption: Option<Vec<Inline>>,
+    },
+    // the placeholder of a nested table, by the id of the table
+    NestedTable(usize),
+}
+
+/// How `write_nodes` writes the nodes, by default back as wikitext.
+pub(crate) trait InlineWriter {
+    /// Write the node in a way of its own, false to write it as wikitext.
+    fn write_node(&self, _out: &mut String, _node: &Inline) -> bool {
+        false
+    }
+
+    /// Write the text, or the markup around the children of a node.
+    fn write_text(&self, out: &mut String, text: &str) {
+        out.push_str(text);
+    }
+}
+
+struct Wikitext;
+
+impl InlineWriter for Wikitext {}
+
+/// Write the nodes as wikitext, the text of a `<nowiki>` is written as it
+/// is. The writer may write some of the nodes in a way of its own.
+pub(crate) fn write_nodes<W: InlineWriter + ?Sized>(
+    out: &mut String,
+    nodes: &[Inline],
+    writer: &W,
+) {
+    for node in nodes {
+        write_node(out, node, writer);
+    }
+}
+
+fn write_node<W: InlineWriter + ?Sized>(out: &mut String, node: &Inline, writer: &W) {
+    if writer.write_node(out, node) {
+        return;
+    }
+    match node {
+        Inline::Text(text) => writer.write_text(out, text),
+        Inline::Link {
+            target,
+            anchor,
+            label,
+        } => {
+            writer.write_text(out, &format!("[[{}", target));
+            if let Some(anchor) = anchor {
+                writer.write_text(out, &format!("#{}", anchor));
+            }
+            if let Some(label) = label {
+                writer.write_text(out, "|");
+                write_nodes(out, label, writer);
+            }
+            writer.write_text(out, "]]");
+        }
+        Inline::ExternalLink { url, label } => {
+            writer.write_text(out, &format!("[{}", url));
+            if let Some(label) = label {
+                writer.write_text(out, " ");
+                write_nodes(out, label, writer);
+            }
+            writer.write_text(out, "]");
+        }
+        Inline::Template { name, args } => {
+            writer.write_text(out, &format!("{{{{{}", name));
+            for arg in args {
+                writer.write_text(out, "|");
+                if let Some(name) = &arg.name {
+                    writer.write_text(out, &format!("{}=", name));
+                }
+                write_nodes(out, &arg.value, writer);
+            }
+            writer.write_text(out, "}}");
+        }
+        Inline::Bold(children) => {
+            writer.write_text(out, "'''");
+            write_nodes(out, children, writer);
+            writer.write_text(out, "'''");
+        }
+        Inline::Italic(children) => {
+            writer.write_text(out, "''");
+            write_nodes(out, children, writer);
+            writer.write_text(out, "''");
+        }
+        Inline::Element {
+            name,
+            attrs,
+            children,
+        } => {
+            let open = match attrs.is_empty() {
+                true => name.clone(),
+                false => format!("{} {}", name, attrs),
+            };
+            if VOID_TAGS.contains(&name.as_str()) {
+                return writer.write_text(out, &format!("<{} />", open));
+            }
+            writer.write_text(out, &format!("<{}>", open));
+            write_nodes(out, children, writer);
+            writer.write_text(out, &format!("</{}>", name));
+        }
+        Inline::Reference { name, content } => {
+            let open = match name {
+                Some(name) => format!("ref name=\"{}\"", name),
+                None => "ref".to_string(),
+            };
+            if content.is_empty() {
+                return writer.write_text(out, &format!("<{} />", open));
+            }
+            writer.write_text(out, &format!("<{}>", open));
+            write_nodes(out, content, writer);
+            writer.write_text(out, "</ref>");
+        }
+        Inline::File {
+            target,
+            options,
+            caption,
+        } => {
+            writer.write_text(out, &format!("[[{}", target));
+            for option in options {
+                writer.write_text(out, &format!("|{}", option));
+            }
+            if let Some(caption) = caption {
+                writer.write_text(out, "|");
+                write_nodes(out, caption, writer);
+            }
+            writer.write_text(out, "]]");
+        }
+        Inline::NestedTable(id) => writer.write_text(out, &nested_table_placeholder(*id)),
+    }
+}
+
+// the node written as wikitext
+impl fmt::Display for Inline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut out = String::new();
+        write_node(&mut out, self, &Wikitext);
+        f.write_str(&out)
+    }
+}
+
+// the length of the url scheme the text starts with
+pub(crate) fn url_scheme_len(text: &str) -> Option<usize> {
+    ["https://", "http://", "ftp://", "mailto:", "//"]
+        .iter()
+        .find(|scheme| {
+            text.get(..scheme.len())
+                .is_some_and(|s| s.eq_ignore_ascii_case(scheme))
+        })
+        .map(|scheme| scheme.len())
+}
+
+/// An argument of a template, `name` is `None` for a positional one.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TemplateArg {
+    pub name: Option<String>,
+    pub value: Vec<Inline>,
+}
+
+// the HTML tags allowed in wikitext, and the tags of the extensions
+// https://www.mediawiki.org/wiki/Help:HTML_in_wikitext
+const HTML_TAGS: [&str; 52] = [
+    "abbr",
+    "b",
+    "bdi",
+    "bdo",
+    "big",
+    "blockquote",
+    "br",
+    "caption",
+    "center",
+    "cite",
+    "code",
+    "data",
+    "dd",
+    "del",
+    "dfn",
+    "div",
+    "dl",
+    "dt",
+    "em",
+    "font",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "i",
+    "ins",
+    "kbd",
+    "li",
+    "mark",
+    "ol",
+    "p",
+    "q",
+    "rb",
+    "ref",
+    "references",
+    "rp",
+    "rt",
+    "ruby",
+    "s",
+    "samp",
+    "small",
+    "span",
+    "strike",
+    "strong",
+    "sub",
+    "sup",
+    "table-ref",
+    "u",
+    "wbr",
+];
+
+// the elements which never have children
+const VOID_TAGS: [&str; 3] = ["br", "hr", "wbr"];
+
+// the options of a file, the other part is the caption
+// https://www.mediawiki.org/wiki/Help:Images#Syntax
+const FILE_OPTIONS: [&str; 20] = [
+    "thumb",
+    "thumbnail",
+    "frame",
+    "framed",
+    "frameless",
+    "border",
+    "left",
+    "right",
+    "center",
+    "centre",
+    "none",
+    "upright",
+    "baseline",
+    "middle",
+    "sub",
+    "super",
+    "text-top",
+    "text-bottom",
+    "top",
+    "bottom",
+];
+
+fn is_file_option(part: &str) -> bool {
+    let part = part.trim().to_ascii_lowercase();
+    let is_size = part.strip_suffix("px").is_some_and(|size| {
+        !size.is_empty() && size.chars().all(|c| c.is_ascii_digit() || c == 'x')
+    });
+    FILE_OPTIONS.contains(&part.as_str())
+        || is_size
+        || ["alt=", "link=", "upright=", "page=", "class=", "lang="]
+            .iter()
+            .any(|key| part.starts_with(key))
+}
+
+pub(crate) fn push_node(nodes: &mut Vec<Inline>, node: Inline) {
+    match (nodes.last_mut(), node) {
+        (_, Inline::Text(text)) if text.is_empty() => {}
+        (Some(Inline::Text(last)), Inline::Text(text)) => *last += &text,
+        (_, node) => nodes.push(node),
+    }
+}
+
+// the parts joined by a `|` as it was in the text
+fn join_parts(parts: Vec<Vec<Inline>>) -> Vec<Inline> {
+    let mut nodes = Vec::new();
+    for (i, part) in parts.into_iter().enumerate() {
+        if i > 0 {
+            push_node(&mut nodes, Inline::Text("|".to_string()));
+        }
+        for node in part {
+            push_node(&mut nodes, node);
+        }
+    }
+    nodes
+}
+
+fn element_node(name: String, attrs: String, children: Vec<Inline>) -> Inline {
+    let attributes = parse_attributes(&attrs);
+    match name.as_str() {
+        "ref" => Inline::Reference {
+            name: attributes.get("name").map(String::from),
+            content: children,
+        },
+        "table-ref" if attributes.get_number("id").is_some() => {
+            Inline::NestedTable(attributes.get_number("id").unwrap())
+        }
+        _ => Inline::Element {
+            name,
+            attrs,
+            children,
+        },
+    }
+}
+
+fn template_arg(part: Vec<Inline>) -> TemplateArg {
+    // a named argument starts with `key=`
+    let named = match part.first() {
+        Some(Inline::Text(text)) => text
+            .split_once('=')
+            .map(|(key, value)| (key.trim().to_string(), value.to_string())),
+        _ => None,
+    };
+    if let Some((key, value)) = named {
+        let mut nodes = Vec::new();
+        push_node(&mut nodes, Inline::Text(value));
+        for node in part.into_iter().skip(1) {
+            push_node(&mut nodes, node);
+        }
+        return TemplateArg {
+            name: Some(key),
+            value: nodes,
+        };
+    }
+    TemplateArg {
+        name: None,
+        value: part,
+    }
+}
+
+// An HTML tag at the start of the text, e.g. `<span class="x">`.
+struct Tag<'s> {
+    name: String,
+    attrs: &'s str,
+    closing: bool,
+    self_closing: bool,
+    len: usize,
+}
+
+fn parse_tag(text: &str) -> Option<Tag<'_>> {
+    let rest = text.strip_prefix('<')?;
+    let (closing, rest) = match rest.strip_prefix('/') {
+        Some(rest) => (true, rest),
+        None => (false, rest),
+    };
+    let name_len = rest
+        .find(|c: char| !c.is_ascii_alphanumeric() && c != '-')
+        .unwrap_or(rest.len());
+    let name = rest[..name_len].to_ascii_lowercase();
+    if !HTML_TAGS.contains(&name.as_str()) {
+        return None;
+    }
+    let after = &rest[name_len..];
+    if !after.starts_with(|c: char| c == '>' || c == '/' || c.is_whitespace()) {
+        return None;
+    }
+    let end = after.find('>')?;
+    let inner = after[..end].trim_end();
+    if inner.contains('<') {
+        return None;
+    }
+    Some(Tag {
+        name,
+        attrs: inner.trim_end_matches('/').trim(),
+        closing,
+        self_closing: inner.ends_with('/'),
+        len: text.len() - after.len() + end + 1,
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum FrameKind {
+    Root,
+    Bold,
+    Italic,
+    Link,
+    Template,
+    ExternalLink { url: String },
+    Element { name: String, attrs: String },
+}
+
+// A markup being read, which is closed by its end token.
+#[derive(Debug)]
+struct Frame {
+    kind: FrameKind,
+    // the markup which opened it, read as text if it is not closed
+    open: String,
+    // the nodes of each part between the `|`, and where the part is in the text
+    parts: Vec<Vec<Inline>>,
+    ranges: Vec<(usize, usize)>,
+}
+
+struct InlineReader<'s> {
+    text: &'s str,
+    frames: Vec<Frame>,
+}
+
+impl<'s> InlineReader<'s> {
+    fn new(text: &'s str) -> Self {
+        let mut reader = InlineReader {
+            text,
+            frames: Vec::new(),
+        };
+        reader.open(FrameKind::Root, "", 0);
+        reader
+    }
+
+    fn push(&mut self, node: Inline) {
+        let frame = self.frames.last_mut().unwrap();
+        push_node(frame.parts.last_mut().unwrap(), node);
+    }
+
+    fn push_text(&mut self, text: &str) {
+        self.push(Inline::Text(text.to_string()));
+    }
+
+    // `start` is where the content starts, after the opening markup
+    fn open(&mut self, kind: FrameKind, open: &str, start: usize) {
+        self.frames.push(Frame {
+            kind,
+            open: open.to_string(),
+            parts: vec![Vec::new()],
+            ranges: vec![(start, start)],
+        });
+    }
+
+    // the index of the nearest frame of the kind, not looking through the
+    // frames `through` does not match
+    fn find(
+        &self,
+        matches: impl Fn(&FrameKind) -> bool,
+        through: impl Fn(&FrameKind) -> bool,
+    ) -> Option<usize> {
+        for (index, frame) in self.frames.iter().enumerate().rev() {
+            if matches(&frame.kind) {
+                return Some(index);
+            }
+            if !through(&frame.kind) {
+                return None;
+            }
+        }
+        None
+    }
+
+    // Close the frame found by its end token at `end`.
+    fn close_top(&mut self, end: usize) {
+        let mut frame = self.frames.pop().unwrap();
+        frame.ranges.last_mut().unwrap().1 = end;
+        let text = self.text;
+        let raw = |index: usize| {
+            let (start, end) = frame.ranges[index];
+            text[start..end].trim()
+        };
+        let node = match &frame.kind {
+            FrameKind::Root => unreachable!("the root is never closed"),
+            FrameKind::Bold | FrameKind::Italic if frame.parts[0].is_empty() => return,
+            FrameKind::Bold => Inline::Bold(frame.parts.remove(0)),
+            FrameKind::Italic => Inline::Italic(frame.parts.remove(0)),
+            FrameKind::Link => {
+                let (target, anchor) = match raw(0).split_once('#') {
+                    Some((target, anchor)) => (target.trim(), Some(anchor.trim().to_string())),
+                    None => (raw(0), None),
+                };
+                let namespace = target.split_once(':').map(|(ns, _)| ns.trim());
+                if namespace.is_some_and(|ns| ns.eq_ignore_ascii_case("file"))
+                    || namespace.is_some_and(|ns| ns.eq_ignore_ascii_case("image"))
+                {
+                    // the last part is the caption, unless it is an option
+                    let mut options: Vec<String> =
+                        (1..frame.parts.len()).map(|i| raw(i).to_string()).collect();
+                    let caption = match options.last() {
+                        Some(last) if !is_file_option(last) => {
+                            options.pop();
+                            frame.parts.pop()
+                        }
+                        _ => None,
+                    };
+                    Inline::File {
+                        target: target.to_string(),
+                        options,
+                        caption,
+                    }
+                } else {
+                    let target = target.to_string();
+                    let label = match frame.parts.len() {
+                        1 => None,
+                        _ => Some(join_parts(frame.parts.split_off(1))),
+                    };
+                    Inline::Link {
+                        target,
+                        anchor,
+                        label,
+                    }
+                }
+            }
+            FrameKind::Template => Inline::Template {
+                name: raw(0).to_string(),
+                args: frame.parts.drain(1..).map(template_arg).collect(),
+            },
+            FrameKind::ExternalLink { url } => {
+                let label = frame.parts.remove(0);
+                Inline::ExternalLink {
+                    url: url.clone(),
+                    label: (!label.is_empty()).then_some(label),
+                }
+            }
+            FrameKind::Element { name, attrs } => {
+                element_node(name.clone(), attrs.clone(), frame.parts.remove(0))
+            }
+        };
+        self.push(node);
+    }
+
+    // Close the frame where it is not closed by its end token.
+    fn abandon_top(&mut self) {
+        match self.frames.last().unwrap().kind {
+            FrameKind::Link | FrameKind::Template | FrameKind::ExternalLink { .. } => {
+                let frame = self.frames.pop().unwrap();
+                self.push(Inline::Text(frame.open));
+                for node in join_parts(frame.parts) {
+                    self.push(node);
+                }
+            }
+            // bold, italic and elements are closed where they are
+            _ => self.close_top(self.text.len()),
+        }
+    }
+
+    // Close the frame at the index by its end token at `end`, and the
+    // frames inside of it which are not closed.
+    fn close(&mut self, index: usize, end: usize) {
+        while self.frames.len() > index + 1 {
+            self.abandon_top();
+        }
+        self.close_top(end);
+    }
+
+    // the end token of a link, a template or an external link
+    fn close_or_text(&mut self, matches: impl Fn(&FrameKind) -> bool, token: &str, offset: usize) {
+        match self.find(matches, |kind| kind != &FrameKind::Root) {
+            Some(index) => self.close(index, offset),
+            None => self.push_text(token),
+        }
+    }
+
+    // `|` starts the next part of a link or a template
+    fn separate(&mut self, offset: usize) {
+        let is_formatting = |kind: &FrameKind| matches!(kind, FrameKind::Bold | FrameKind::Italic);
+        let index = self.find(
+            |kind| matches!(kind, FrameKind::Link | FrameKind::Template),
+            is_formatting,
+        );
+        match index {
+            Some(index) => {
+                while self.frames.len() > index + 1 {
+                    self.abandon_top();
+                }
+                let frame = self.frames.last_mut().unwrap();
+                frame.ranges.last_mut().unwrap().1 = offset;
+                frame.parts.push(Vec::new());
+                frame.ranges.push((offset + 1, offset + 1));
+            }
+            None => self.push_text("|"),
+        }
+    }
+
+    // Open the bold or italic, or close it if it is open. The ones inside
+    // of it are closed, and opened again after it.
+    fn toggle(&mut self, kind: FrameKind, token: &str, offset: usize) {
+        let is_formatting = |kind: &FrameKind| matches!(kind, FrameKind::Bold | FrameKind::Italic);
+        match self.find(|k| k == &kind, is_formatting) {
+            Some(index) => {
+                let inner: Vec<FrameKind> = self.frames[index + 1..]
+                    .iter()
+                    .map(|frame| frame.kind.clone())
+                    .collect();
+                self.close(index, offset);
+                for kind in inner {
+                    self.open(kind, "", offset + token.len());
+                }
+            }
+            None => self.open(kind, token, offset + token.len()),
+        }
+    }
+
+    // a tag which is not in a protected region
+    fn tag(&mut self, tag: Tag, offset: usize) {
+        let raw = &self.text[offset..offset + tag.len];
+        if tag.closing {
+            let name = tag.name.clone();
+            let index = self.find(
+                |kind| matches!(kind, FrameKind::Element { name: n, .. } if *n == name),
+                |kind| kind != &FrameKind::Root,
+            );
+            match index {
+                Some(index) => self.close(index, offset),
+                None => self.push_text(raw),
+            }
+        } else if tag.self_closing || VOID_TAGS.contains(&tag.name.as_str()) {
+            self.push(element_node(tag.name, tag.attrs.to_string(), Vec::new()));
+        } else {
+            let kind = FrameKind::Element {
+                name: tag.name,
+                attrs: tag.attrs.to_string(),
+            };
+            self.open(kind, raw, offset + tag.len);
+        }
+    }
+
+    // a comment, `<nowiki>`, `<pre>` or `<math>`, whose content is not wikitext
+    fn region(&mut self, markup: &str) {
+        let lower = markup.to_ascii_lowercase();
+        if lower.starts_with("<!--") {
+            return;
+        }
+        let (open_end, close_start) = match (markup.find('>'), markup.rfind("</")) {
+            (Some(open_end), Some(close_start)) if open_end < close_start => {
+                (open_end, close_start)
+            }
+            // `<nowiki/>` has no content
+            _ => return,
+        };
+        let content = markup[open_end + 1..close_start].to_string();
+        if lower.starts_with("<nowiki") {
+            self.push(Inline::Text(content));
+            return;
+        }
+        let open = markup[1..open_end].trim_end_matches('/');
+        let (name, attrs) = open.split_once(char::is_whitespace).unwrap_or((open, ""));
+        self.push(Inline::Element {
+            name: name.to_ascii_lowercase(),
+            attrs: attrs.trim().to_string(),
+            children: vec![Inline::Text(content)],
+        });
+    }
+
+    fn finish(mut self) -> Vec<Inline> {
+        while self.frames.len() > 1 {
+            self.abandon_top();
+        }
+        self.frames.pop().unwrap().parts.remove(0)
+    }
+}
+
+/// Parse the text of cells into `Inline` nodes.
+#[derive(Debug, Clone)]
+pub struct InlineParser {
+    cell_tokenizer: TypedTokenizer<CellTextSpecialTokens>,
+}
+
+impl Default for InlineParser {
+    fn default() -> Self {
+        InlineParser::new(Tokenizer::build(get_all_cell_text_special_tokens()))
+    }
+}
+
+impl InlineParser {
+    /// The tokenizer is built from `get_all_cell_text_special_tokens`.
+    pub fn new(cell_tokenizer: Tokenizer) -> Self {
+        InlineParser {
+            cell_tokenizer: TypedTokenizer::new(cell_tokenizer),
+        }
+    }
+
+    pub fn parse(&self, text: &str) -> Vec<Inline> {
+        let regions = protected_regions(text);
+        let mut reader = InlineReader::new(text);
+        // the text before it is already read, e.g. the url of a link
+        let mut consumed = 0;
+        let mut offset = 0;
+        let mut tokens = self.cell_tokenizer.tokens(text).peekable();
+        while let Some(token) = tokens.next() {
+            let start = offset;
+            let end = start + token.as_str().len();
+            offset = end;
+            if end <= consumed {
+                continue;
+            }
+            if start < consumed {
+                reader.push_text(&text[consumed..end]);
+                continue;
+            }
+            if let Some(region) = regions.iter().find(|region| region.start == start) {
+                reader.region(&text[region.clone()]);
+                consumed = region.end;
+                continue;
+            }
+            match token {
+                Token::Special(CellTextSpecialTokens::LinkStart) => {
+                    reader.open(FrameKind::Link, "[[", end)
+                }
+                Token::Special(CellTextSpecialTokens::LinkEnd) => {
+                    reader.close_or_text(|kind| kind == &FrameKind::Link, "]]", start)
+                }
+                Token::Special(CellTextSpecialTokens::TemplateStart) => {
+                    reader.open(FrameKind::Template, "{{", end)
+                }
+                Token::Special(CellTextSpecialTokens::TemplateEnd) => {
+                    reader.close_or_text(|kind| kind == &FrameKind::Template, "}}", start)
+                }
+                Token::Special(CellTextSpecialTokens::Sep) => reader.separate(start),
+                Token::Special(CellTextSpecialTokens::Blockquote) => {
+                    // `'''''` is read as `'''` and `''`, the innermost one is closed first
+                    if tokens.peek() == Some(&Token::Special(CellTextSpecialTokens::Italic)) {
+                        tokens.next();
+                        offset += 2;
+                        let italic_open = reader.frames.last().unwrap().kind == FrameKind::Italic;
+                        let (first, second) = match italic_open {
+                            true => ((FrameKind::Italic, "''"), (FrameKind::Bold, "'''")),
+                            false => ((FrameKind::Bold, "'''"), (FrameKind::Italic, "''")),
+                        };
+                        reader.toggle(first.0, first.1, start);
+                        reader.toggle(second.0, second.1, start + first.1.len());
+                    } else {
+                        reader.toggle(FrameKind::Bold, "'''", start);
+                    }
+                }
+                Token::Special(CellTextSpecialTokens::Italic) => {
+                    reader.toggle(FrameKind::Italic, "''", start)
+                }
+                Token::Special(CellTextSpecialTokens::ExternalLinkStart) => {
+                    let rest = &text[end..];
+                    match url_scheme_len(rest) {
+                        Some(_) => {
+                            // the url ends before a space or any markup
+                            let url_len = rest
+                                .find(|c: char| c.is_whitespace() || "[]{}|<>\"'".contains(c))
+                                .unwrap_or(rest.len());
+                            let after = &rest[url_len..];
+                            let label_start = end + rest.len() - after.trim_start().len();
+                            let kind = FrameKind::ExternalLink {
+                                url: rest[..url_len].to_string(),
+                            };
+                            reader.open(kind, &text[start..label_start], label_start);
+                            consumed = label_start;
+                        }
+                        None => reader.push_text("["),
+                    }
+                }
+                Token::Special(CellTextSpecialTokens::ExternalLinkEnd) => reader.close_or_text(
+                    |kind| matches!(kind, FrameKind::ExternalLink { .. }),
+                    "]",
+                    start,
+                ),
+                Token::Special(CellTextSpecialTokens::HtmlTagStart) => {
+                    match parse_tag(&text[start..]) {
+                        Some(tag) => {
+                            consumed = start + tag.len;
+                            reader.tag(tag, start);
+                        }
+                        None => reader.push_text("<"),
+                    }
+                }
+                Token::Special(CellTextSpecialTokens::HtmlTagEnd) => reader.push_text(">"),
+                Token::Text(text) => reader.push_text(text),
+            }
+        }
+        reader.finish()
+    }
+}
+
+/// Parse the text of a cell into `Inline` nodes, by an `InlineParser`
+/// built once and shared by all the calls.
+pub fn parse_inline(text: &str) -> Vec<Inline> {
+    static INLINE_PARSER: OnceLock<InlineParser> = OnceLock::new();
+    INLINE_PARSER.get_or_init(InlineParser::default).parse(text)
+}
+
+// The label of a link, None if the link reads as its target, e.g.
+// `[[Xenon]]` or the pipe trick `[[Help:Link|]]`.
+pub(crate) fn link_label(label: &Option<Vec<Inline>>) -> Option<&[Inline]> {
+    label
+        .as_deref()
+        .filter(|label| !plain_text(label).trim().is_empty())
+}
+
+// the text of a link which reads as its target, e.g. `Xenon#Uses`
+pub(crate) fn link_target_text(target: &str, anchor: &Option<String>) -> String {
+    let target = target.trim().trim_start_matches(':');
+    match anchor {
+        Some(anchor) => format!("{}#{}", target, anchor),
+        None => target.to_string(),
+    }
+}
+
+// The text of a link without a label of its own. With the pipe trick
+// `[[Help:Link (x)|]]` it is the title without its prefix and its
+// parenthesised suffix, `Link`, otherwise the target.
+pub(crate) fn link_text(
+    target: &str,
+    anchor: &Option<String>,
+    label: &Option<Vec<Inline>>,
+) -> String {
+    if label.is_none() {
+        return link_target_text(target, anchor);
+    }
+    let target = target.trim().trim_start_matches(':');
+    // a namespace or an interwiki prefix, e.g. `Help:` or `w:`
+    let title = match target.split_once(':') {
+        Some((prefix, title))
+            if !prefix.is_empty()
+                && prefix
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || matches!(c, ' ' | '_' | '-')) =>
+        {
+            title
+        }
+        _ => target,
+    };
+    let title = match title.find('(') {
+        Some(start) if start > 0 && title.ends_with(')') => &title[..start],
+        _ => title,
+    };
+    title.trim().to_string()
+}
+
+/// The text of the nodes as it reads on the page, without the templates,
+/// the references and the files.
+pub fn plain_text(nodes: &[Inline]) -> String {
+    let mut out = String::new();
+    for node in nodes {
+        match node {
+            Inline::Text(text) => out += text,
+            Inline::Link {
+                target,
+                anchor,
+                label,
+            } => match link_label(label) {
+                Some(label) => out += &plain_text(label),
+                None => out += &link_text(target, anchor, label),
+            },
+            Inline::ExternalLink {
+                label: Some(label), ..
+            } => out += &plain_text(label),
+            Inline::ExternalLink { url, .. } => out += url,
+            Inline::Bold(children) | Inline::Italic(children) => out += &plain_text(children),
+            Inline::Element { name, .. } if name == "br" || name == "hr" => out.push('\n'),
+            Inline::Element { children, .. } => out += &plain_text(children),
+            Inline::Template { .. }
+            | Inline::Reference { .. }
+            | Inline::File { .. }
+            | Inline::NestedTable(_) => {}
+        }
+    }
+    out
+}
@@ -0,0 +1,112 @@
+use crate::parser::Event;
+use crate::table::{tables_from_events, Table};
+use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
+
+// Write the tables as JSON Lines, one table on each line with the page it
+// is from, e.g. to read it by `jq`.
+// https://jsonlines.org/
+
+/// The page of the tables, the fields which are None are left out.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PageMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revision_id: Option<u64>,
+}
+
+/// A line of the output, e.g.
+/// `{"page_id":5659,"title":"Chemical element","index":0,"table":{...}}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TableLine {
+    #[serde(flatten)]
+    pub page: PageMetadata,
+    // the index of the table in the page
+    pub index: usize,
+    pub table: Table,
+}
+
+// the same as `TableLine`, to write a table without cloning it
+#[derive(Serialize)]
+struct TableLineRef<'a> {
+    #[serde(flatten)]
+    page: &'a PageMetadata,
+    index: usize,
+    table: &'a Table,
+}
+
+#[cfg(feature = "dump")]
+impl From<&crate::dump::DumpPage> for PageMetadata {
+    fn from(page: &crate::dump::DumpPage) -> Self {
+        PageMetadata {
+            page_id: Some(page.page_id),
+            title: Some(page.title.clone()),
+            namespace: Some(page.namespace),
+            revision_id: Some(page.revision_id),
+        }
+    }
+}
+
+pub struct JsonlWriter<W: Write> {
+    writer: W,
+    // the number of lines written
+    lines: usize,
+}
+
+impl<W: Write> JsonlWriter<W> {
+    pub fn new(writer: W) -> Self {
+        JsonlWriter { writer, lines: 0 }
+    }
+
+    pub fn lines(&self) -> usize {
+        self.lines
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    pub fn write_table(
+        &mut self,
+        page: &PageMetadata,
+        index: usize,
+        table: &Table,
+    ) -> io::Result<()> {
+        let line = TableLineRef { page, index, table };
+        serde_json::to_writer(&mut self.writer, &line)?;
+        self.writer.write_all(b"\n")?;
+        self.lines += 1;
+        Ok(())
+    }
+
+    /// Write the tables of a page, one line for each.
+    pub fn write_tables(&mut self, page: &PageMetadata, tables: &[Table]) -> io::Result<()> {
+        for (index, table) in tables.iter().enumerate() {
+            self.write_table(page, index, table)?;
+        }
+        Ok(())
+    }
+
+    /// Write every table of the events, including the nested tables, in the
+    /// order they start.
+    pub fn write_events<I>(&mut self, page: &PageMetadata, events: I) -> io::Result<()>
+    where
+        I: IntoIterator<Item = Event>,
+    {
+        self.write_tables(page, &tables_from_events(events))
+    }
+
+    #[cfg(feature = "dump")]
+    pub fn write_page(&mut self, page: &crate::dump::DumpPage) -> io::Result<()> {
+        self.write_tables(&page.into(), &page.tables)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
@@ -1,20 +1,77 @@
+pub mod attributes;
+pub mod batch;
+pub mod cst;
+pub mod diagnostics;
+pub mod document;
+#[cfg(feature = "dump")]
+pub mod dump;
+pub mod export;
+pub mod grid;
+pub mod html;
+pub mod inline;
+#[cfg(feature = "serde")]
+pub mod jsonl;
+pub mod links;
+pub mod markdown;
+#[cfg(feature = "dump")]
+pub mod multistream;
 pub mod parser;
+pub mod serialize;
+pub mod table;
+pub mod templates;
 pub mod tokenizer;
 pub mod utils;
 use pyo3::prelude::*;
 
 /// A Python module implemented in Rust.
 #[pymodule]
-fn wikitext_table_parser(_py: Python, m: &PyModule) -> PyResult<()> {
+fn wikitext_table_parser(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(tokenizer::get_all_cell_text_special_tokens, m)?)?;
     m.add_function(wrap_pyfunction!(tokenizer::get_all_table_special_tokens, m)?)?;
+    m.add_function(wrap_pyfunction!(parser::nested_table_placeholder, m)?)?;
     m.add_class::<tokenizer::Tokenizer>()?;
     m.add_class::<parser::WikitextTableParser>()?;
     m.add_class::<parser::Event>()?;
     m.add_class::<parser::CellType>()?;
+    m.add_class::<parser::Span>()?;
+    m.add_class::<diagnostics::Diagnostic>()?;
+    m.add_class::<diagnostics::DiagnosticCode>()?;
+    m.add_class::<diagnostics::Severity>()?;
+    m.add_function(wrap_pyfunction!(table::parse_tables, m)?)?;
+    m.add_class::<table::Table>()?;
+    m.add_class::<table::Row>()?;
+    m.add_class::<table::Cell>()?;
+    m.add_class::<utils::TemplatePolicy>()?;
     Ok(())
 }
 
+#[cfg(test)]
+mod tests {
+    use crate::parser::WikitextTableParser;
+    use crate::tokenizer::{
+        get_all_cell_text_special_tokens, get_all_table_special_tokens, Tokenizer,
+    };
+
+    // the table tokenizer and the cell tokenizer of a parser
+    pub(crate) fn tokenizers() -> (Tokenizer, Tokenizer) {
+        (
+            Tokenizer::build(get_all_table_special_tokens()),
+            Tokenizer::build(get_all_cell_text_special_tokens()),
+        )
+    }
+
+    pub(crate) fn parser(content: &str, clean_cell_text: bool) -> WikitextTableParser {
+        let (table_tokenizer, cell_tokenizer) = tokenizers();
+        WikitextTableParser::new(table_tokenizer, cell_tokenizer, content, clean_cell_text)
+    }
+
+    // The path of a sample file, e.g. `wikitext_dumps/sample.xml`, None if it
+    // is missing: the samples are not in the package, only in the repository.
+    pub(crate) fn sample(path: &str) -> Option<&str> {
+        std::path::Path::new(path).exists().then_some(path)
+    }
+}
+
 #[cfg(test)]
 mod test_tokenizer {
     use crate::tokenizer;
@@ -26,17 +83,84 @@ mod test_tokenizer {
         let tokenizer = tokenizer::Tokenizer::build(tokenizer::get_all_table_special_tokens());
         let out = tokenizer.tokenize(&raw_string);
         assert_eq!(out.join(" / "), expect_result.join(" / "));
+
+        // the start of a special token which is not completed is split into chars
+        assert_eq!(tokenizer.tokenize("</x"), vec!["<", "/", "x"]);
+    }
+
+    #[test]
+    fn tokens() {
+        let special_tokens = tokenizer::get_all_table_special_tokens();
+        let tokenizer = tokenizer::Tokenizer::build(special_tokens.clone());
+        let raw_string = "\n{|123||\n|}<><nowiki>é\n|-";
+        let out: Vec<&str> = tokenizer.tokens(raw_string).map(|(_, s)| s).collect();
+        assert_eq!(
+            out,
+            vec!["\n{|", "123", "||", "\n|}", "<>", "<nowiki>", "é", "\n|-"]
+        );
+        // borrowed from the text
+        let (_, first) = tokenizer.tokens(raw_string).next().unwrap();
+        assert_eq!(first.as_ptr(), raw_string.as_ptr());
+
+        // the kind of a special token is its index
+        let kinds: Vec<String> = tokenizer
+            .tokens("<nowi\n|x<!--->")
+            .map(|(kind, s)| match kind {
+                tokenizer::TokenKind::Special(index) => {
+                    assert_eq!(special_tokens[index], s);
+                    format!("special {:?}", s)
+                }
+                tokenizer::TokenKind::Text => format!("text {:?}", s),
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                "text \"<nowi\"",
+                "special \"\\n|\"",
+                "text \"x\"",
+                "special \"<!--\"",
+                "text \"->\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn typed_tokens() {
+        use tokenizer::{TableSpecialTokens, Token, TypedTokenizer};
+
+        // a special token which is not a `TableSpecialTokens` is read as text
+        let mut special_tokens = tokenizer::get_all_table_special_tokens();
+        special_tokens.push(String::from("[["));
+        let tokenizer =
+            TypedTokenizer::<TableSpecialTokens>::new(tokenizer::Tokenizer::build(special_tokens));
+        let out: Vec<_> = tokenizer.tokens("\n{|\n| [[a]] || b\n|}").collect();
+        assert_eq!(
+            out,
+            vec![
+                Token::Special(TableSpecialTokens::TableStart),
+                Token::Special(TableSpecialTokens::TableDataCell),
+                Token::Text(" "),
+                Token::Text("[["),
+                Token::Text("a]] "),
+                Token::Special(TableSpecialTokens::TableDataCell2),
+                Token::Text(" b"),
+                Token::Special(TableSpecialTokens::TableEnd),
+            ]
+        );
     }
 }
 
 #[cfg(test)]
 mod test_parser {
-    use crate::parser::{Event, WikitextTableParser};
+    use crate::diagnostics::DiagnosticCode;
+    use crate::parser::{nested_table_placeholder, Event, WikitextTableParser};
+    use crate::tests::{parser, sample, tokenizers};
     use crate::tokenizer::{
         get_all_cell_text_special_tokens, get_all_table_special_tokens, Tokenizer,
     };
     use std::fs::File;
-    use std::io::Read;
+    use std::io::{BufReader, Cursor, Read};
 
     fn test_parse_struct_table(path: String, expect_rows: i32, expect_cols: i32) {
         /* Test a wiki text table that has expect number of rows and cols */
@@ -49,7 +173,7 @@ mod test_parser {
             }
         };
         let mut content: String = String::new();
-        if let Err(_) = file.read_to_string(&mut content) {
+        if file.read_to_string(&mut content).is_err() {
             return;
         }
 
@@ -65,7 +189,7 @@ mod test_parser {
             WikitextTableParser::new(table_tokenizer, cell_tokenizer, &content,true);
         for event in wikitext_table_parser {
             match event {
-                Event::RowStyle{text:row_style} => {
+                Event::RowStyle { text: row_style, .. } => {
                     if count_rows > 0 {
                         // do not work just after parse the first row, which is a table headr.
                         assert_eq!(expect_cols, count_cols);
@@ -74,12 +198,12 @@ mod test_parser {
                     count_cols = 0;
                     println!("----- {:?} -----", row_style);
                 }
-                Event::ColEnd{text} => {
+                Event::ColEnd { text, .. } => {
                     count_cols += 1;
                     println!("col: {:?}#", text);
                 }
-                Event::TableStart{} => count_table_start += 1,
-                Event::TableEnd{} => count_table_end += 1,
+                Event::TableStart { .. } => count_table_start += 1,
+                Event::TableEnd { .. } => count_table_end += 1,
                 _ => {}
             }
         }
@@ -97,7 +221,7 @@ mod test_parser {
             }
         };
         let mut content: String = String::new();
-        if let Err(_) = file.read_to_string(&mut content) {
+        if file.read_to_string(&mut content).is_err() {
             return;
         }
 
@@ -107,11 +231,8 @@ mod test_parser {
             WikitextTableParser::new(table_tokenizer, cell_tokenizer, &content,true);
 
         for event in wikitext_table_parser {
-            match event {
-                Event::TableCaption{text:caption }=> {
-                    assert_eq!(caption, expect_caption);
-                }
-                _ => {}
+            if let Event::TableCaption { text: caption, .. } = event {
+                assert_eq!(caption, expect_caption);
             }
         }
     }
@@ -141,6 +262,253 @@ mod test_parser {
         test_parse_struct_table(String::from("wikitext_tables/5.txt"), 14, 7)
     }
 
+    #[test]
+    fn test_nested_table() {
+        let Some(path) = sample("wikitext_tables/12.txt") else {
+            return;
+        };
+        let content = std::fs::read_to_string(path).unwrap();
+        let wikitext_table_parser = parser(&content, true);
+
+        let mut tables = Vec::new();
+        let mut cols = Vec::new();
+        for event in wikitext_table_parser {
+            match event {
+                Event::TableStart { id, depth, .. } => tables.push(("start", id, depth)),
+                Event::TableEnd { id, depth, .. } => tables.push(("end", id, depth)),
+                Event::ColEnd { text, .. } => cols.push(text.trim().to_string()),
+                _ => {}
+            }
+        }
+        assert_eq!(
+            tables,
+            vec![("start", 0, 0), ("start", 1, 1), ("end", 1, 1), ("end", 0, 0)]
+        );
+        // cells of the inner table come first, since they end first
+        assert_eq!(
+            cols,
+            vec![
+                "Name",
+                "Detail",
+                "Alpha",
+                "a1",
+                "a2",
+                "a3",
+                "a4",
+                &nested_table_placeholder(1),
+                "Beta",
+                "beta detail"
+            ]
+        );
+
+        // a nested table which ends with `|-` keeps the text of the outer cell
+        let tables = crate::table::parse_tables("{|\n| before\n{|\n| inner\n|-\n|}\nafter\n|}");
+        assert_eq!(
+            tables[0].rows[0].cells[0].text.trim(),
+            format!("before\n{}\nafter", nested_table_placeholder(1))
+        );
+    }
+
+    #[test]
+    fn test_event_spans() {
+        for i in 1..=12 {
+            let content = std::fs::read_to_string(format!("wikitext_tables/{}.txt", i)).unwrap();
+            let wikitext_table_parser = parser(&content, false);
+            for event in wikitext_table_parser {
+                let span = event.span();
+                let source = &content[span.start..span.end];
+                // line and column point at the start of the span
+                let before = &content[..span.start];
+                assert_eq!(span.line, before.matches('\n').count() + 1);
+                assert_eq!(
+                    span.column,
+                    before.rsplit('\n').next().unwrap().chars().count() + 1
+                );
+                match event {
+                    Event::TableStart { .. } => assert_eq!(source, "{|"),
+                    Event::TableEnd { .. } => assert_eq!(source, "|}"),
+                    Event::ColStart { .. } => {
+                        assert!(["|", "||", "!", "!!"].contains(&source))
+                    }
+                    Event::TableStyle { text, .. }
+                    | Event::TableCaptionStyle { text, .. }
+                    | Event::TableCaption { text, .. }
+                    | Event::RowStyle { text, .. }
+                    | Event::ColStyle { text, .. } => assert_eq!(source, text),
+                    // the placeholder of a nested table is not in the source
+                    Event::ColEnd { text, .. } if !text.contains("<table-ref") => {
+                        assert_eq!(source, text)
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let Some(path) = sample("wikitext_tables/1.txt") else {
+            return;
+        };
+        let content = std::fs::read_to_string(path).unwrap();
+        let oxygen = parser(&content, false)
+            .find(|event| matches!(event, Event::ColEnd { text, .. } if text == "[[Oxygen]]"))
+            .unwrap();
+        assert_eq!((oxygen.span().line, oxygen.span().column), (7, 2));
+    }
+
+    #[test]
+    fn test_lazy_events() {
+        // an unterminated table is only reported once all the tokens are read
+        let content = "{|\n| a\n|-\n| b\n";
+        let mut parser = parser(content, true);
+        assert!(matches!(parser.next(), Some(Event::TableStart { .. })));
+        assert!(parser.diagnostics().is_empty());
+        assert_eq!(parser.by_ref().count(), 14);
+        assert_eq!(parser.diagnostics().len(), 1);
+        assert!(parser.next().is_none());
+
+        // a large table does not take quadratic time
+        let content = "{|\n".to_string() + &"|-\n| a || b\n".repeat(20_000) + "|}";
+        let cols = crate::tests::parser(&content, true)
+            .filter(|event| matches!(event, Event::ColEnd { .. }))
+            .count();
+        assert_eq!(cols, 40_000);
+    }
+
+    #[test]
+    fn test_parse_from_reader() {
+        // tokens and chars split by the end of a chunk, and markup which is
+        // read again when it is not closed
+        let mut contents: Vec<String> = (1..=12)
+            .map(|i| std::fs::read_to_string(format!("wikitext_tables/{}.txt", i)).unwrap())
+            .collect();
+        contents.push("{|\n| a <nowiki>|}\n| b\n|}".to_string());
+        contents.push("{|\n| a <!-- x --> || b <pre>\n|-\n| c\n|}".to_string());
+        contents.push("{|\n| <PRE class=\"a||b\">x\n|y</Pre > || c <nowiki>\n|}".to_string());
+        for (i, content) in contents.iter().enumerate() {
+            let (table_tokenizer, cell_tokenizer) = tokenizers();
+            let expect: Vec<String> = parser(content, true)
+                .map(|event| format!("{:?}", event))
+                .collect();
+            for capacity in [1, 2, 5, 4096] {
+                let reader = BufReader::with_capacity(capacity, Cursor::new(content.clone()));
+                let events: Vec<String> = WikitextTableParser::from_reader(
+                    table_tokenizer.clone(),
+                    cell_tokenizer.clone(),
+                    reader,
+                    true,
+                )
+                .map(|event| format!("{:?}", event))
+                .collect();
+                assert_eq!(events, expect, "content {}, capacity {}", i, capacity);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_unclosed_from_reader() {
+        // the text of an unclosed comment or tag is read in chunks
+        let lines = "a line of text, a | and a [[link]]\n".repeat(2000);
+        for content in [
+            format!("{{|\n| a || b <!-- c\n{}|}}", lines),
+            format!("{{|\n| a || b\n|}}\n<!--\n{}", lines),
+            format!("{{|\n| a || b <nowiki> c\n{}|}}", lines),
+            format!("{{|\n| a || b <pre>\n{}</pr", lines),
+        ] {
+            let (table_tokenizer, cell_tokenizer) = tokenizers();
+            let mut string_parser = parser(&content, true);
+            let expect: Vec<String> = string_parser
+                .by_ref()
+                .map(|event| format!("{:?}", event))
+                .collect();
+            let expect_diagnostics = string_parser.diagnostics().to_vec();
+            for capacity in [7, 4096] {
+                let reader = BufReader::with_capacity(capacity, Cursor::new(content.clone()));
+                let mut parser = WikitextTableParser::from_reader(
+                    table_tokenizer.clone(),
+                    cell_tokenizer.clone(),
+                    reader,
+                    true,
+                );
+                let events: Vec<String> =
+                    parser.by_ref().map(|event| format!("{:?}", event)).collect();
+                assert_eq!(events, expect, "capacity {}", capacity);
+                assert_eq!(parser.diagnostics(), expect_diagnostics);
+            }
+        }
+
+        // a tag is not kept for more than 1 MiB, its closing tag after that
+        // is not found
+        let content = format!(
+            "{{|\n| a <nowiki>{}</nowiki> b\n| c\n|}}",
+            "x".repeat((1 << 20) + 1)
+        );
+        let reader = BufReader::new(Cursor::new(content));
+        let (table_tokenizer, cell_tokenizer) = tokenizers();
+        let mut parser =
+            WikitextTableParser::from_reader(table_tokenizer, cell_tokenizer, reader, false);
+        let cols = parser
+            .by_ref()
+            .filter(|event| matches!(event, Event::ColEnd { .. }))
+            .count();
+        assert_eq!(cols, 2);
+        assert_eq!(parser.diagnostics().len(), 1);
+        assert_eq!(
+            parser.diagnostics()[0].message,
+            "`</nowiki>` is not found in 1048576 bytes"
+        );
+    }
+
+    #[test]
+    fn test_parse_from_large_reader() {
+        // the text before the table is read in chunks, see `benches/parser.rs`
+        // for the time it takes
+        for line in [
+            "some text, a [[link|name]] and <b>bold</b> text - ok!\n",
+            "text without a char which starts a token ",
+        ] {
+            let content = line.repeat(4000) + "\n{|\n| a || b\n|}";
+            let reader = BufReader::with_capacity(4096, Cursor::new(content));
+            let (table_tokenizer, cell_tokenizer) = tokenizers();
+            let cols: Vec<String> =
+                WikitextTableParser::from_reader(table_tokenizer, cell_tokenizer, reader, true)
+                    .filter_map(|event| match event {
+                        Event::ColEnd { text, .. } => Some(text),
+                        _ => None,
+                    })
+                    .collect();
+            assert_eq!(cols, vec!["a", "b"]);
+        }
+    }
+
+    #[test]
+    fn test_parse_from_broken_reader() {
+        struct Broken;
+        impl Read for Broken {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("broken"))
+            }
+        }
+
+        // events are read before the reader fails
+        let content = "{|\n| a || b\n|-\n| c || d\n|}\n";
+        let reader = BufReader::with_capacity(4, Cursor::new(content).chain(Broken));
+        let (table_tokenizer, cell_tokenizer) = tokenizers();
+        let mut parser =
+            WikitextTableParser::from_reader(table_tokenizer, cell_tokenizer, reader, true);
+        assert!(matches!(parser.next(), Some(Event::TableStart { .. })));
+        assert!(parser.diagnostics().is_empty());
+
+        let cols: Vec<String> = parser
+            .by_ref()
+            .filter_map(|event| match event {
+                Event::ColEnd { text, .. } => Some(text.trim().to_string()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(cols, vec!["a", "b", "c", "d"]);
+        assert_eq!(parser.diagnostics().len(), 1);
+        assert_eq!(parser.diagnostics()[0].code, DiagnosticCode::ReadError);
+    }
+
     #[test]
     fn test_table_caption_1() {
         let path = String::from("wikitext_tables/1.txt");
@@ -148,3 +516,1927 @@ mod test_parser {
         test_table_caption(path, expect_caption.to_string())
     }
 }
+
+#[cfg(test)]
+mod test_table {
+    use crate::parser::{nested_table_placeholder, CellType};
+    use crate::serialize::tables_to_wikitext;
+    use crate::table::parse_tables;
+
+    #[test]
+    fn test_parse_tables() {
+        let content = std::fs::read_to_string("wikitext_tables/1.txt").unwrap();
+        let tables = parse_tables(&content);
+        assert_eq!(tables.len(), 1);
+
+        let table = &tables[0];
+        assert_eq!(
+            table.attrs,
+            "class=\"wikitable sortable\" style=\"float: left; margin: 0 0 1em 1em;\""
+        );
+        assert!(table.caption.as_ref().unwrap().starts_with("Seawater"));
+        assert_eq!(table.rows.len(), 11);
+        assert!(matches!(table.rows[0].cells[0].kind, CellType::HeaderCell));
+        assert_eq!(table.rows[1].cells[0].text, "[[Oxygen]]");
+        assert_eq!(table.rows[1].cells[1].text, "85.84");
+    }
+
+    #[test]
+    fn test_caption_attrs() {
+        let content = "{|\n|+ style=\"color:red\" | Caption\n| a\n|}\n{|\n|+ Plain\n| b\n|}";
+        let tables = parse_tables(content);
+        assert_eq!(tables[0].caption.as_deref().map(str::trim), Some("Caption"));
+        assert_eq!(tables[0].caption_attrs, "style=\"color:red\" |");
+        assert_eq!(
+            tables[0].caption_attributes().get("style"),
+            Some("color:red")
+        );
+        assert_eq!(tables[1].caption.as_deref(), Some("Plain"));
+        assert_eq!(tables[1].caption_attrs, "");
+    }
+
+    #[test]
+    fn test_parse_nested_tables() {
+        let content = std::fs::read_to_string("wikitext_tables/12.txt").unwrap();
+        let tables = parse_tables(&content);
+        assert_eq!(tables.len(), 2);
+        assert_eq!((tables[0].id, tables[0].depth), (0, 0));
+        assert_eq!((tables[1].id, tables[1].depth), (1, 1));
+
+        let cell = &tables[0].rows[1].cells[1];
+        assert_eq!(cell.attrs, "style=\"padding:0\" |");
+        assert_eq!(cell.text.trim(), nested_table_placeholder(1));
+        assert_eq!(tables[1].rows.len(), 2);
+        assert_eq!(tables[1].rows[1].cells[1].text.trim(), "a4");
+        assert_eq!(tables[0].children, vec![1]);
+        assert!(tables[1].children.is_empty());
+
+        // a placeholder written in the source is not a nested table
+        let tables =
+            parse_tables("{|\n| <table-ref id=\"0\"/>\n{|\n| <table-ref id=\"1\"/>\n|}\n|}");
+        assert_eq!(tables[0].children, vec![1]);
+        assert!(tables[1].children.is_empty());
+
+        // the kind and the attributes of the outer cell are kept over the nested table
+        let tables = parse_tables("{|\n! a=1 | outer header\n{|\n| b=2 | inner\n|}\n|}");
+        let outer = &tables[0].rows[0].cells[0];
+        assert!(matches!(outer.kind, CellType::HeaderCell));
+        assert_eq!(outer.attrs, "a=1 |");
+        let inner = &tables[1].rows[0].cells[0];
+        assert!(matches!(inner.kind, CellType::DataCell));
+        assert_eq!(inner.attrs, "b=2 |");
+    }
+
+    #[test]
+    fn test_nested_tables_outside_of_cells() {
+        // in the caption, and between `|-` and the first cell
+        let content =
+            "{|\n|+ cap\n{|\n| in caption\n|}\n|- style=\"x\"\n{|\n| in row\n|}\n| out\n|}\n";
+        let tables = parse_tables(content);
+        assert_eq!(tables.len(), 3);
+        assert_eq!(
+            tables[0].caption.as_deref(),
+            Some(format!("cap\n{}", nested_table_placeholder(1)).as_str())
+        );
+        assert_eq!(
+            tables[0].rows[0].attrs,
+            format!("style=\"x\"\n{}", nested_table_placeholder(2))
+        );
+        // the outer table is not closed by the `|}` of the nested ones
+        assert_eq!(tables[0].rows.len(), 1);
+        assert_eq!(tables[0].rows[0].cells[0].text.trim(), "out");
+        assert_eq!((tables[1].depth, tables[2].depth), (1, 1));
+        assert_eq!(tables[1].rows[0].cells[0].text.trim(), "in caption");
+        assert_eq!(tables[2].rows[0].cells[0].text.trim(), "in row");
+
+        // and they are written back where they were found
+        let wikitext = tables_to_wikitext(&tables);
+        assert_eq!(
+            wikitext,
+            "{|\n|+ cap\n{|\n|-\n| in caption\n|}\n|- style=\"x\"\n{|\n|-\n| in row\n|}\n| out\n|}"
+        );
+        let parsed = parse_tables(&wikitext);
+        assert_eq!(parsed[0].caption, tables[0].caption);
+        assert_eq!(parsed[0].rows[0].attrs, tables[0].rows[0].attrs);
+        assert_eq!(parsed.len(), 3);
+    }
+}
+
+#[cfg(test)]
+mod test_grid {
+    use crate::grid::{Grid, GridIssueKind};
+    use crate::table::parse_tables;
+
+    #[test]
+    fn test_grid_with_spans() {
+        let content = std::fs::read_to_string("wikitext_tables/11.txt").unwrap();
+        let tables = parse_tables(&content);
+        let grid = Grid::from_table(&tables[0]);
+        assert_eq!(grid.width, 8);
+        assert_eq!(grid.height(), tables[0].rows.len());
+        assert!(grid.issues.is_empty());
+
+        // `! rowspan="2" | 年份` covers (0, 0) and (1, 0)
+        assert!(grid.get(0, 0).unwrap().is_origin);
+        assert!(!grid.get(1, 0).unwrap().is_origin);
+        assert_eq!(grid.cell(1, 0).unwrap().text.trim(), "年份");
+        // `! colspan="3" | 全部空间` covers (0, 1) to (0, 3)
+        assert_eq!(grid.cell(0, 3).unwrap().text.trim(), "全部空间");
+        assert_eq!(grid.cell(1, 1).unwrap().text.trim(), "第一名");
+        assert_eq!(grid.cell(1, 5).unwrap().text.trim(), "第一名");
+        assert_eq!(grid.cell(2, 7).unwrap().text.trim(), "Mountain（0.0k）");
+    }
+
+    #[test]
+    fn test_grid_issues() {
+        let content = "{|\n| a || b\n|-\n| c || d || e\n|-\n| rowspan=\"3\" | f\n|}";
+        let tables = parse_tables(content);
+        let grid = Grid::from_table(&tables[0]);
+        let issues: Vec<_> = grid.issues.iter().map(|i| (i.kind, i.row)).collect();
+        assert_eq!(
+            issues,
+            vec![
+                (GridIssueKind::Overflow, 2),
+                (GridIssueKind::Overflow, 1),
+                (GridIssueKind::Underflow, 2)
+            ]
+        );
+        assert_eq!(grid.slots[2].len(), 3);
+        assert_eq!(grid.get(2, 1), None);
+    }
+
+    #[test]
+    fn test_grid_row_covered_by_rowspans() {
+        // the second row has no cells of its own, `c` and `d` are in the third
+        let content = "{|\n|-\n| rowspan=2 | a\n| rowspan=2 | b\n|-\n|-\n| c\n| d\n|-\n|}";
+        let tables = parse_tables(content);
+        assert_eq!(tables[0].rows.len(), 3);
+        let grid = Grid::from_table(&tables[0]);
+        assert!(grid.issues.is_empty());
+        assert_eq!(grid.cell(1, 1).unwrap().text.trim(), "b");
+        assert_eq!(grid.cell(2, 0).unwrap().text.trim(), "c");
+        assert_eq!(grid.cell(2, 1).unwrap().text.trim(), "d");
+    }
+
+    #[test]
+    fn test_grid_huge_spans() {
+        let content = "{|\n| colspan=\"99999999999\" | a\n|-\n| b || colspan=\"18446744073709551615\" rowspan=\"18446744073709551615\" | c\n|}";
+        let tables = parse_tables(content);
+        let grid = Grid::from_table(&tables[0]);
+        // the spans are capped as MediaWiki does
+        assert_eq!(grid.width, 1000);
+        assert_eq!(grid.slots[1].len(), 1001);
+        assert_eq!(grid.cell(1, 1000).unwrap().text.trim(), "c");
+        assert_eq!(
+            grid.issues
+                .iter()
+                .map(|i| (i.kind, i.row))
+                .collect::<Vec<_>>(),
+            vec![(GridIssueKind::Overflow, 1), (GridIssueKind::Overflow, 1)]
+        );
+    }
+
+    #[test]
+    fn test_grid_overlapping_spans() {
+        let content = "{|\n| a || rowspan=\"2\" | b || c\n|-\n| colspan=\"3\" | d\n|}";
+        let tables = parse_tables(content);
+        let grid = Grid::from_table(&tables[0]);
+        // the slot taken by `b` is kept by it
+        assert_eq!(grid.cell(1, 0).unwrap().text.trim(), "d");
+        assert_eq!(grid.cell(1, 1).unwrap().text.trim(), "b");
+        assert_eq!(grid.cell(1, 2).unwrap().text.trim(), "d");
+        let issues: Vec<_> = grid
+            .issues
+            .iter()
+            .map(|i| (i.kind, i.row, i.expected, i.actual))
+            .collect();
+        assert_eq!(issues, vec![(GridIssueKind::Conflict, 1, 3, 2)]);
+    }
+}
+
+#[cfg(test)]
+mod test_attributes {
+    use crate::attributes::{parse_attributes, parse_style};
+    use crate::table::parse_tables;
+
+    #[test]
+    fn test_parse_attributes() {
+        let attributes =
+            parse_attributes("class=\"wikitable\" align=right title='a \"b\"' hidden CLASS=sortable |");
+        let entries: Vec<_> = attributes.iter().collect();
+        assert_eq!(
+            entries,
+            vec![
+                ("class", "sortable"),
+                ("align", "right"),
+                ("title", "a \"b\""),
+                ("hidden", "")
+            ]
+        );
+        assert_eq!(attributes.get("Align"), Some("right"));
+        assert!(attributes.contains_key("hidden"));
+    }
+
+    #[test]
+    fn test_parse_style() {
+        let style = parse_style(
+            "text-align:center; background: url(\"a;b.png\") ; Width: 30%;;text-align: left",
+        );
+        let declarations: Vec<_> = style.iter().collect();
+        assert_eq!(
+            declarations,
+            vec![
+                ("text-align", "left"),
+                ("background", "url(\"a;b.png\")"),
+                ("width", "30%")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_table_attributes() {
+        let content = std::fs::read_to_string("wikitext_tables/9.txt").unwrap();
+        let tables = parse_tables(&content);
+        let table = &tables[0];
+        assert_eq!(table.attributes().get("width"), Some("65%"));
+        assert_eq!(table.attributes().style().get("text-align"), Some("center"));
+
+        let row = &table.rows[0];
+        assert_eq!(row.attributes().get("align"), Some("center"));
+        assert_eq!(row.attributes().style().get("background"), Some("cornflowerblue"));
+        assert_eq!(row.cells[0].attributes().get("width"), Some("30%"));
+        assert_eq!(table.rows[1].cells[2].attributes().get("align"), Some("left"));
+    }
+}
+
+#[cfg(test)]
+mod test_diagnostics {
+    use crate::diagnostics::{DiagnosticCode, Severity};
+    use crate::parser::Event;
+    use crate::table::tables_from_events;
+    use crate::tests::parser;
+
+    #[test]
+    fn test_unterminated_table() {
+        let content = "{|\n| a || b\n|-\n| c ||\n{|\n| d";
+        let mut parser = parser(content, true);
+        let events: Vec<Event> = parser.by_ref().collect();
+        let codes: Vec<_> = parser.diagnostics().iter().map(|d| d.code).collect();
+        assert_eq!(
+            codes,
+            vec![
+                DiagnosticCode::UnterminatedTable,
+                DiagnosticCode::UnterminatedTable
+            ]
+        );
+        assert_eq!(parser.diagnostics()[0].severity, Severity::Error);
+        // the inner table is reported first, at its `{|`
+        assert_eq!(parser.diagnostics()[0].span.line, 5);
+        assert_eq!(parser.diagnostics()[1].span.line, 1);
+
+        let tables = tables_from_events(events);
+        assert_eq!(tables.len(), 2);
+        assert_eq!(tables[0].rows.len(), 2);
+        assert_eq!(tables[1].rows[0].cells[0].text.trim(), "d");
+    }
+
+    #[test]
+    fn test_recovery_warnings() {
+        let content = "|-\n{|\n| a\n|-\n|+ caption\n|-\n| b\n|}";
+        let mut parser = parser(content, true);
+        let tables = tables_from_events(parser.by_ref());
+        let codes: Vec<_> = parser.diagnostics().iter().map(|d| d.code).collect();
+        assert_eq!(
+            codes,
+            vec![
+                DiagnosticCode::RowOutsideTable,
+                DiagnosticCode::CaptionAfterRow
+            ]
+        );
+        assert!(parser
+            .diagnostics()
+            .iter()
+            .all(|d| d.severity == Severity::Warning));
+        assert_eq!(tables[0].caption.as_deref(), Some("caption"));
+        assert_eq!(tables[0].rows.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_strict() {
+        for i in 1..=12 {
+            let content = std::fs::read_to_string(format!("wikitext_tables/{}.txt", i)).unwrap();
+            assert!(parser(&content, true).parse_strict().is_ok());
+        }
+
+        let error = parser("{|\n| a", true).parse_strict().unwrap_err();
+        assert_eq!(error.diagnostics.len(), 1);
+        assert_eq!(
+            error.to_string(),
+            "Error UnterminatedTable at 1:1: the table is not closed by `|}`"
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_document {
+    use crate::document::{protected_regions, scan_tables};
+    use crate::tests::sample;
+
+    #[test]
+    fn test_protected_regions() {
+        let text = "a<!-- {| -->b<nowiki>{|</nowiki>c<NoWiki/><pre class=\"x\">{|</pre><math>x</math>";
+        let regions: Vec<&str> = protected_regions(text)
+            .into_iter()
+            .map(|region| &text[region])
+            .collect();
+        assert_eq!(
+            regions,
+            vec![
+                "<!-- {| -->",
+                "<nowiki>{|</nowiki>",
+                "<NoWiki/>",
+                "<pre class=\"x\">{|</pre>",
+                "<math>x</math>"
+            ]
+        );
+        // an unclosed comment runs to the end
+        assert_eq!(protected_regions("a<!-- b"), vec![1..7]);
+    }
+
+    #[test]
+    fn test_scan_tables() {
+        let article = "intro\n\
+            == First ==\n\
+            <!--\n{|\n| commented\n|}\n-->\n\
+            {| class=\"wikitable\"\n| a\n|\n{|\n| nested\n|}\n|}\n\
+            === Second ===\n\
+            <syntaxhighlight lang=\"text\">\n{|\n| code\n|}\n</syntaxhighlight>\n\
+            <nowiki>\n{|</nowiki>\n\
+            : {|\n| indented\n|}\n\
+            {|\n| b\n|}\n";
+        let tables = scan_tables(article);
+        assert_eq!(tables.len(), 2);
+
+        assert_eq!(tables[0].heading.as_deref(), Some("First"));
+        assert!(tables[0].source.starts_with("{| class="));
+        assert!(tables[0].source.ends_with("|}\n|}"));
+        assert_eq!(&article[tables[0].offset..tables[0].offset + 2], "{|");
+        let parsed = tables[0].tables();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[1].rows[0].cells[0].text.trim(), "nested");
+
+        assert_eq!(tables[1].heading.as_deref(), Some("Second"));
+        // an indented table is text, as for the parser
+        assert_eq!(tables[1].source, "{|\n| b\n|}");
+
+        // an indented nested table is text too, its `|}` ends the table
+        let article = "{|\n| a\n:{|\n| b\n|}\n|}\n";
+        let tables = scan_tables(article);
+        assert_eq!(tables.len(), 1);
+        assert_eq!(tables[0].source, "{|\n| a\n:{|\n| b\n|}");
+        assert_eq!(tables[0].tables().len(), 1);
+    }
+
+    #[test]
+    fn test_tables_with_protected_tags() {
+        // the markup of a table in the tags of `protected_regions` is text
+        let article = "{|\n\
+            | <math>a || b</math> || c\n\
+            |-\n\
+            | <syntaxhighlight lang=\"text\">\n{|\n| code\n|}\n</syntaxhighlight>\n\
+            | <SOURCE>x || y</SOURCE>\n\
+            |}";
+        let tables = scan_tables(article);
+        assert_eq!(tables.len(), 1);
+        let parsed = tables[0].tables();
+        assert_eq!(parsed.len(), 1);
+        let texts: Vec<Vec<&str>> = parsed[0]
+            .rows
+            .iter()
+            .map(|row| row.cells.iter().map(|cell| cell.text.trim()).collect())
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                vec!["<math>a || b</math>", "c"],
+                vec![
+                    "<syntaxhighlight lang=\"text\">\n{|\n| code\n|}\n</syntaxhighlight>",
+                    "<SOURCE>x || y</SOURCE>"
+                ]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scan_tables_in_article() {
+        let Some(path) = sample("wikitext_tables/9.txt") else {
+            return;
+        };
+        let content = std::fs::read_to_string(path).unwrap();
+        let tables = scan_tables(&content);
+        assert_eq!(tables.len(), 1);
+        assert_eq!(tables[0].heading.as_deref(), Some("主要演員"));
+        assert_eq!(tables[0].tables()[0].rows.len(), 5);
+    }
+}
+
+#[cfg(test)]
+mod test_opaque_spans {
+    use crate::diagnostics::DiagnosticCode;
+    use crate::document::protected_regions;
+    use crate::table::{parse_tables, tables_from_events};
+    use crate::tests::parser;
+
+    const CONTENT: &str = "<!--\n{|\n| not a table\n|}\n-->\n\
+        {|\n\
+        | a <nowiki>||</nowiki> b || <nowiki>c|d</nowiki>\n\
+        |-\n\
+        <!-- | phantom -->\n\
+        | e <pre>\n|}</pre>\n\
+        |}";
+
+    #[test]
+    fn test_keep_opaque_spans() {
+        let tables = parse_tables(CONTENT);
+        assert_eq!(tables.len(), 1);
+        let rows = &tables[0].rows;
+        let texts: Vec<Vec<&str>> = rows
+            .iter()
+            .map(|row| row.cells.iter().map(|cell| cell.text.trim()).collect())
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                vec!["a <nowiki>||</nowiki> b", "<nowiki>c|d</nowiki>"],
+                vec!["e <pre>\n|}</pre>"]
+            ]
+        );
+        assert_eq!(rows[0].cells[1].attrs, "");
+        assert_eq!(rows[1].attrs, "<!-- | phantom -->");
+    }
+
+    #[test]
+    fn test_strip_opaque_spans() {
+        let mut parser = parser(CONTENT, false);
+        parser.strip_opaque_spans = true;
+        let tables = tables_from_events(parser);
+        let rows = &tables[0].rows;
+        assert_eq!(rows[0].cells[0].text.trim(), "a || b");
+        assert_eq!(rows[0].cells[1].text.trim(), "c|d");
+        assert_eq!(rows[1].attrs, "");
+        assert_eq!(rows[1].cells[0].text.trim(), "e \n|}");
+    }
+
+    #[test]
+    fn test_unclosed_nowiki() {
+        let mut parser = parser("{|\n| a <nowiki> b\n| c\n|}", false);
+        let tables = tables_from_events(parser.by_ref());
+        let texts: Vec<&str> = tables[0].rows[0]
+            .cells
+            .iter()
+            .map(|cell| cell.text.trim())
+            .collect();
+        assert_eq!(texts, vec!["a <nowiki> b", "c"]);
+        assert_eq!(parser.diagnostics().len(), 1);
+        assert_eq!(parser.diagnostics()[0].code, DiagnosticCode::UnclosedMarkup);
+    }
+
+    #[test]
+    fn test_closing_tags_without_opening() {
+        let tables = parse_tables("{|\n| x --> y || a </pre> b || c </nowiki> d\n|}");
+        let texts: Vec<&str> = tables[0].rows[0]
+            .cells
+            .iter()
+            .map(|cell| cell.text.trim())
+            .collect();
+        assert_eq!(texts, vec!["x --> y", "a </pre> b", "c </nowiki> d"]);
+    }
+
+    #[test]
+    fn test_opaque_tags_with_attributes_and_case() {
+        let content = "{|\n| <pre class=\"x\">a\n|b</pre> || c\n|-\n| <NOWIKI>||</NOWIKI> d || e <!-- f\n|| g --> h\n|}";
+        let tables = parse_tables(content);
+        let texts: Vec<Vec<&str>> = tables[0]
+            .rows
+            .iter()
+            .map(|row| row.cells.iter().map(|cell| cell.text.trim()).collect())
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                vec!["<pre class=\"x\">a\n|b</pre>", "c"],
+                vec!["<NOWIKI>||</NOWIKI> d", "e <!-- f\n|| g --> h"]
+            ]
+        );
+
+        let mut parser = parser(content, false);
+        parser.strip_opaque_spans = true;
+        let tables = tables_from_events(parser.by_ref());
+        assert_eq!(tables[0].rows[0].cells[0].text.trim(), "a\n|b");
+        assert_eq!(tables[0].rows[1].cells[0].text.trim(), "|| d");
+        assert_eq!(tables[0].rows[1].cells[1].text.trim(), "e  h");
+        assert!(parser.diagnostics().is_empty());
+    }
+
+    #[test]
+    fn test_many_unclosed_tags() {
+        // the unclosed tags are read as text, see `benches/parser.rs` for the
+        // time it takes
+        for tag in ["<pre>a ", "<NOWIKI>a ", "<pre a "] {
+            let text = format!("{{|\n| {}\n|}}", tag.repeat(2000));
+            let mut parser = parser(&text, false);
+            let tables = tables_from_events(parser.by_ref());
+            assert!(protected_regions(&text).is_empty());
+            assert_eq!(tables[0].rows[0].cells[0].text.trim(), tag.repeat(2000).trim());
+            // only the first tag is reported, the ones after it are known to
+            // be unclosed, a tag without `>` is not a tag
+            let reported = usize::from(tag.contains('>'));
+            assert_eq!(parser.diagnostics().len(), reported);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "dump"))]
+mod test_dump {
+    use crate::batch::{parse_document, BatchError, Document};
+    use crate::diagnostics::DiagnosticCode;
+    use crate::dump::{open_dump, DumpReader};
+    use crate::parser::nested_table_placeholder;
+    use crate::tests::sample;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_read_dump() {
+        for path in ["wikitext_dumps/sample.xml", "wikitext_dumps/sample.xml.bz2"] {
+            let Some(path) = sample(path) else {
+                return;
+            };
+            let pages: Vec<_> = open_dump(path).unwrap().map(|page| page.unwrap()).collect();
+            let titles: Vec<&str> = pages.iter().map(|page| page.title.as_str()).collect();
+            assert_eq!(
+                titles,
+                vec![
+                    "Chemical element",
+                    "Chemical elements",
+                    "Template:Element box",
+                    "Noble gas"
+                ]
+            );
+
+            let page = &pages[0];
+            assert_eq!(
+                (page.page_id, page.namespace, page.revision_id),
+                (5659, 0, 1181452271)
+            );
+            assert_eq!(page.tables.len(), 2);
+            let table = &page.tables[0];
+            assert_eq!(table.attrs, "class=\"wikitable sortable\"");
+            assert_eq!(table.caption.as_deref(), Some("Lightest elements"));
+            assert_eq!(table.rows.len(), 3);
+            // entities are decoded
+            assert_eq!(
+                table.rows[2].cells[2].text,
+                "[[Helium]] <ref>Named after the Sun.</ref>"
+            );
+            assert_eq!(page.tables[1].rows[0].cells[1].text, "3 & more");
+
+            assert_eq!(pages[2].namespace, 10);
+            assert!(pages[3].tables.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_skip_pages() {
+        let Some(path) = sample("wikitext_dumps/sample.xml") else {
+            return;
+        };
+        let mut reader = open_dump(path).unwrap();
+        reader.skip_redirects = true;
+        reader.namespaces = Some(vec![0]);
+        let titles: Vec<String> = reader.map(|page| page.unwrap().title).collect();
+        assert_eq!(titles, vec!["Chemical element", "Noble gas"]);
+    }
+
+    #[test]
+    fn test_dump_documents() {
+        let Some(path) = sample("wikitext_dumps/sample.xml.bz2") else {
+            return;
+        };
+        let documents: Vec<_> = open_dump(path)
+            .unwrap()
+            .documents()
+            .map(|document| document.unwrap())
+            .collect();
+        assert_eq!(documents.len(), 4);
+        assert_eq!(documents[1].id, 5660);
+        assert_eq!(documents[1].text, "#REDIRECT [[Chemical element]]");
+        assert_eq!(
+            (documents[2].namespace, documents[2].revision_id),
+            (Some(10), Some(1100000001))
+        );
+        let output = parse_document(documents[2].clone(), false);
+        assert_eq!(
+            (output.namespace, output.revision_id),
+            (Some(10), Some(1100000001))
+        );
+    }
+
+    #[test]
+    fn test_tables_of_page() {
+        // the tables of `scan_tables`, the ids go on from one to the next
+        let text = "intro\n\
+            <syntaxhighlight lang=\"text\">\n{|\n| code\n|}\n</syntaxhighlight>\n\
+            {|\n| a\n{|\n| b\n|}\n|}\n\
+            {| <nowiki>\n| c\n{|\n| d\n|}\n|}\n";
+        let xml = format!(
+            "<mediawiki><page><title>A</title><ns>0</ns><id>1</id>\
+             <revision><id>2</id><text>{}</text></revision></page></mediawiki>",
+            text.replace('<', "&lt;")
+        );
+        let page = DumpReader::new(Cursor::new(xml)).next().unwrap().unwrap();
+        let ids: Vec<(usize, Vec<usize>)> = page
+            .tables
+            .iter()
+            .map(|table| (table.id, table.children.clone()))
+            .collect();
+        assert_eq!(
+            ids,
+            vec![(0, vec![1]), (1, vec![]), (2, vec![3]), (3, vec![])]
+        );
+        assert_eq!(
+            page.tables[2].rows[0].cells[0].text.trim(),
+            format!("c\n{}", nested_table_placeholder(3))
+        );
+        assert_eq!(page.tables[3].rows[0].cells[0].text.trim(), "d");
+
+        let document = Document {
+            text: text.to_string(),
+            ..Default::default()
+        };
+        let output = parse_document(document.clone(), false);
+        assert_eq!(output.result.unwrap().len(), 4);
+        // the spans of the diagnostics are the ones in the page
+        let diagnostics = match parse_document(document, true).result {
+            Err(BatchError::Strict(error)) => error.diagnostics,
+            result => panic!("{:?}", result),
+        };
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, DiagnosticCode::UnclosedMarkup);
+        let span = diagnostics[0].span;
+        assert_eq!(span.start, text.find("<nowiki>").unwrap());
+        assert_eq!((span.line, span.column), (13, 4));
+    }
+
+    #[test]
+    fn test_broken_dump() {
+        let xml = "<mediawiki><page><title>A</title><ns>0</ns></revision></page></mediawiki>";
+        let mut reader = DumpReader::new(Cursor::new(xml));
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+    }
+}
+
+#[cfg(test)]
+mod test_batch {
+    use crate::batch::{parse_batch, BatchError, BatchOptions, Document};
+
+    fn documents() -> Vec<Document> {
+        (0..200)
+            .map(|id| Document {
+                id,
+                title: format!("Page {}", id),
+                // every 10th table is not closed
+                text: match id % 10 {
+                    0 => format!("{{|\n| {} || a\n", id),
+                    _ => format!("{{|\n| {} || a\n|-\n| b || c\n|}}", id),
+                },
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_batch_ordered() {
+        let options = BatchOptions {
+            ordered: true,
+            threads: Some(4),
+            ..Default::default()
+        };
+        let mut ids = Vec::new();
+        let stats = parse_batch(documents(), &options, |output| {
+            let tables = output.result.unwrap();
+            assert_eq!(tables[0].rows[0].cells[0].text.trim(), output.id.to_string());
+            ids.push(output.id);
+        });
+        assert_eq!(ids, (0..200).collect::<Vec<_>>());
+        assert_eq!((stats.documents, stats.failures, stats.tables), (200, 0, 200));
+        assert!(stats.bytes > 0);
+    }
+
+    #[test]
+    fn test_parse_batch_failures() {
+        let options = BatchOptions {
+            strict: true,
+            ..Default::default()
+        };
+        let mut failed = Vec::new();
+        let stats = parse_batch(documents(), &options, |output| {
+            if let Err(BatchError::Strict(error)) = output.result {
+                assert_eq!(error.diagnostics.len(), 1);
+                failed.push(output.id);
+            }
+        });
+        // not ordered
+        failed.sort();
+        assert_eq!(failed, (0..200).step_by(10).collect::<Vec<_>>());
+        assert_eq!((stats.documents, stats.failures, stats.tables), (200, 20, 180));
+    }
+}
+
+#[cfg(all(test, feature = "dump"))]
+mod test_multistream {
+    use crate::dump::DumpReader;
+    use crate::multistream::{IndexEntry, IndexReader, MultistreamDump, PageIndex, PageKey};
+    use crate::tests::sample;
+    use bzip2::read::{BzDecoder, MultiBzDecoder};
+    use bzip2::write::BzEncoder;
+    use bzip2::Compression;
+    use std::io::{BufReader, Cursor, Write};
+
+    fn compress(text: &str) -> Vec<u8> {
+        let mut encoder = BzEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(text.as_bytes()).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    // the sample dump as a multistream dump and its index, the header and
+    // the footer are streams of their own, None without the sample
+    fn multistream() -> Option<(Vec<u8>, String)> {
+        let xml = std::fs::read_to_string(sample("wikitext_dumps/sample.xml")?).unwrap();
+        let first_page = xml.find("  <page>").unwrap();
+        let third_page = xml.match_indices("  <page>").nth(2).unwrap().0;
+        let footer = xml.find("</mediawiki>").unwrap();
+        let streams = [
+            &xml[..first_page],
+            &xml[first_page..third_page],
+            &xml[third_page..footer],
+            &xml[footer..],
+        ];
+
+        let mut dump = Vec::new();
+        let mut index = String::new();
+        for (i, stream) in streams.iter().enumerate() {
+            let offset = dump.len();
+            dump.extend(compress(stream));
+            if i == 0 || i == streams.len() - 1 {
+                continue;
+            }
+            for page in stream.split("<page>").skip(1) {
+                let id = &page[page.find("<id>").unwrap() + 4..page.find("</id>").unwrap()];
+                let title =
+                    &page[page.find("<title>").unwrap() + 7..page.find("</title>").unwrap()];
+                index += &format!("{}:{}:{}\n", offset, id, title);
+            }
+        }
+        Some((dump, index))
+    }
+
+    #[test]
+    fn test_read_index() {
+        let Some((_, index)) = multistream() else {
+            return;
+        };
+        let compressed = compress(&index);
+        let reader = BufReader::new(BzDecoder::new(compressed.as_slice()));
+        let entries: Vec<IndexEntry> = IndexReader::new(reader).map(|e| e.unwrap()).collect();
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[0].offset, entries[1].offset);
+        assert_ne!(entries[1].offset, entries[2].offset);
+        assert_eq!(entries[2].page_id, 7001);
+        assert_eq!(entries[2].title, "Template:Element box");
+
+        let mut reader = IndexReader::new(Cursor::new("1:2:A\nbroken\n"));
+        assert!(reader.next().unwrap().is_ok());
+        assert!(reader.next().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_page_index() {
+        let Some((_, index)) = multistream() else {
+            return;
+        };
+        // a title given twice, the first entry is found as by `find`
+        let index = format!("{}99:7001:Noble gas\n", index);
+        let pages = PageIndex::read(IndexReader::new(Cursor::new(&index))).unwrap();
+        assert_eq!(pages.len(), 5);
+        for key in [
+            PageKey::Title("Chemical element"),
+            PageKey::Id(21140),
+            PageKey::Title("Noble gas"),
+            PageKey::Id(7001),
+        ] {
+            let found = IndexReader::new(Cursor::new(&index)).find(key).unwrap();
+            assert_eq!(pages.get(key), found.as_ref());
+        }
+        assert_eq!(
+            pages.get(PageKey::Title("Noble gas")).unwrap().page_id,
+            21140
+        );
+        assert!(pages.get(PageKey::Title("Missing")).is_none());
+        assert!(pages.get(PageKey::Id(1)).is_none());
+
+        assert!(PageIndex::read(IndexReader::new(Cursor::new("1:2:A\nbroken\n"))).is_err());
+    }
+
+    #[test]
+    fn test_read_page() {
+        let Some((dump, index)) = multistream() else {
+            return;
+        };
+        let mut multistream = MultistreamDump::new(Cursor::new(dump.clone()));
+        for key in [PageKey::Title("Chemical element"), PageKey::Id(21140)] {
+            let entry = IndexReader::new(Cursor::new(&index))
+                .find(key)
+                .unwrap()
+                .unwrap();
+            let page = multistream.read_page(&entry).unwrap().unwrap();
+            assert!(entry.matches(PageKey::Id(page.page_id)));
+            assert!(entry.matches(PageKey::Title(&page.title)));
+        }
+
+        let entry = IndexReader::new(Cursor::new(&index))
+            .find(PageKey::Title("Chemical element"))
+            .unwrap()
+            .unwrap();
+        let page = multistream.read_page(&entry).unwrap().unwrap();
+        assert_eq!(page.tables.len(), 2);
+        assert_eq!(page.tables[0].caption.as_deref(), Some("Lightest elements"));
+
+        // the page is not in the stream
+        let wrong = IndexEntry {
+            page_id: 21140,
+            ..entry
+        };
+        assert!(multistream.read_page(&wrong).unwrap().is_none());
+
+        // the whole dump is still read as one
+        let pages = DumpReader::new(BufReader::new(MultiBzDecoder::new(Cursor::new(dump))));
+        assert_eq!(pages.count(), 4);
+    }
+}
+
+#[cfg(test)]
+mod test_export {
+    use crate::export::{
+        table_to_csv, write_events, CellCleaning, CsvFormat, CsvOptions, HeaderRows, SpanFill,
+    };
+    use crate::table::parse_tables;
+    use crate::tests::parser;
+
+    #[test]
+    fn test_csv() {
+        let content = "{|\n! a !! b\n|-\n| 1, 2 || say \"hi\"\n|-\n| line\nbreak || x\n|}";
+        let tables = parse_tables(content);
+        let csv = table_to_csv(&tables[0], &CsvOptions::default());
+        assert_eq!(
+            csv,
+            "a,b\r\n\"1, 2\",\"say \"\"hi\"\"\"\r\n\"line\nbreak\",x\r\n"
+        );
+
+        let options = CsvOptions {
+            format: CsvFormat::Tsv,
+            cleaning: CellCleaning::Raw,
+            ..Default::default()
+        };
+        let tsv = table_to_csv(&tables[0], &options);
+        assert_eq!(tsv, "a\tb\n1, 2\tsay \"hi\"\nline break\tx\n");
+
+        let options = CsvOptions {
+            format: CsvFormat::Tsv,
+            cleaning: CellCleaning::Collapse,
+            header_rows: HeaderRows::Skip,
+            ..Default::default()
+        };
+        let tsv = table_to_csv(&tables[0], &options);
+        assert_eq!(tsv, "1, 2\tsay \"hi\"\nline break\tx\n");
+    }
+
+    #[test]
+    fn test_csv_spans() {
+        let content = "{|\n! rowspan=\"2\" | year !! colspan=\"2\" | rank\n|-\n! 1st !! 2nd\n|-\n| 2002 || colspan=\"2\" | a\n|}";
+        let tables = parse_tables(content);
+
+        let csv = table_to_csv(&tables[0], &CsvOptions::default());
+        assert_eq!(csv, "year,rank,rank\r\nyear,1st,2nd\r\n2002,a,a\r\n");
+
+        let options = CsvOptions {
+            span_fill: SpanFill::Empty,
+            ..Default::default()
+        };
+        let csv = table_to_csv(&tables[0], &options);
+        assert_eq!(csv, "year,rank,\r\n,1st,2nd\r\n2002,a,\r\n");
+
+        let options = CsvOptions {
+            header_rows: HeaderRows::Merge,
+            ..Default::default()
+        };
+        let csv = table_to_csv(&tables[0], &options);
+        assert_eq!(csv, "year,rank 1st,rank 2nd\r\n2002,a,a\r\n");
+    }
+
+    #[test]
+    fn test_write_events() {
+        let content = std::fs::read_to_string("wikitext_tables/11.txt").unwrap();
+        let parser = parser(&content, false);
+        let mut buf = Vec::new();
+        let options = CsvOptions {
+            header_rows: HeaderRows::Merge,
+            ..Default::default()
+        };
+        let count = write_events(&mut buf, parser, &options).unwrap();
+        assert_eq!(count, parse_tables(&content).len());
+        let csv = String::from_utf8(buf).unwrap();
+        let header = csv.lines().next().unwrap();
+        assert_eq!(
+            header,
+            "年份,全部空间 第一名,全部空间 第二名,全部空间 第三名,年份,条目空间 第一名,条目空间 第二名,条目空间 第三名"
+        );
+        // every line has all the columns
+        assert!(csv.lines().all(|line| line.split(',').count() == 8));
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod test_jsonl {
+    use crate::jsonl::{JsonlWriter, PageMetadata, TableLine};
+    use crate::parser::Event;
+    use crate::table::parse_tables;
+    use crate::tests::parser;
+
+    #[test]
+    fn test_serde_events() {
+        let content = std::fs::read_to_string("wikitext_tables/11.txt").unwrap();
+        let events: Vec<Event> = parser(&content, false).collect();
+        let json = serde_json::to_string(&events).unwrap();
+        let back: Vec<Event> = serde_json::from_str(&json).unwrap();
+        assert_eq!(format!("{:?}", events), format!("{:?}", back));
+
+        let json = serde_json::to_value(&events[0]).unwrap();
+        assert_eq!(json["TableStart"]["depth"], 0);
+        assert_eq!(json["TableStart"]["span"]["line"], 1);
+    }
+
+    #[test]
+    fn test_write_jsonl() {
+        let content = std::fs::read_to_string("wikitext_tables/1.txt").unwrap();
+        let tables = parse_tables(&content);
+        let page = PageMetadata {
+            page_id: Some(1),
+            title: Some("Seawater".to_string()),
+            ..Default::default()
+        };
+        let mut writer = JsonlWriter::new(Vec::new());
+        writer.write_tables(&page, &tables).unwrap();
+        writer.write_tables(&page, &tables).unwrap();
+        assert_eq!(writer.lines(), tables.len() * 2);
+
+        let output = String::from_utf8(writer.into_inner()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), tables.len() * 2);
+        // the fields which are None are left out
+        assert!(lines[0].starts_with("{\"page_id\":1,\"title\":\"Seawater\",\"index\":0,"));
+        let line: TableLine = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(line.page, page);
+        assert_eq!(line.table.rows.len(), tables[0].rows.len());
+        assert_eq!(
+            line.table.rows[1].cells[0].text,
+            tables[0].rows[1].cells[0].text
+        );
+    }
+
+    #[cfg(feature = "dump")]
+    #[test]
+    fn test_write_dump_pages() {
+        let mut writer = JsonlWriter::new(Vec::new());
+        let Some(path) = crate::tests::sample("wikitext_dumps/sample.xml") else {
+            return;
+        };
+        for page in crate::dump::open_dump(path).unwrap() {
+            writer.write_page(&page.unwrap()).unwrap();
+        }
+        let output = String::from_utf8(writer.into_inner()).unwrap();
+        let lines: Vec<TableLine> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[1].index, 1);
+        assert_eq!(lines[1].page.page_id, Some(5659));
+        assert_eq!(lines[1].page.namespace, Some(0));
+        assert_eq!(lines[1].page.revision_id, Some(1181452271));
+    }
+}
+
+#[cfg(test)]
+mod test_serialize {
+    use crate::attributes::parse_attributes;
+    use crate::parser::CellType;
+    use crate::serialize::{events_to_wikitext, table_to_wikitext, tables_to_wikitext};
+    use crate::table::{parse_tables, Table};
+    use crate::tests::parser;
+
+    // the tables without the whitespace of the text, the `<nowiki>` of the
+    // text and the attributes, and the formatting of the attributes
+    fn normalize(tables: &[Table]) -> Vec<String> {
+        // the comments are kept, an unclosed one is written back as text
+        let unwrap = |text: &str| text.replace("<nowiki>", "").replace("</nowiki>", "");
+        let text = |text: &str| unwrap(text).split_whitespace().collect::<Vec<_>>().join(" ");
+        let attrs = |attrs: &str| parse_attributes(&unwrap(attrs));
+        tables
+            .iter()
+            .map(|table| {
+                let rows: Vec<String> = table
+                    .rows
+                    .iter()
+                    .map(|row| {
+                        let cells: Vec<String> = row
+                            .cells
+                            .iter()
+                            .map(|cell| {
+                                let header = matches!(cell.kind, CellType::HeaderCell);
+                                format!("{} {:?} {}", header, attrs(&cell.attrs), text(&cell.text))
+                            })
+                            .collect();
+                        format!("{:?} {:?}", attrs(&row.attrs), cells)
+                    })
+                    .collect();
+                format!(
+                    "{} {} {:?} {:?} {:?} {:?}",
+                    table.id,
+                    table.depth,
+                    table.caption.as_deref().map(text),
+                    attrs(&table.caption_attrs),
+                    attrs(&table.attrs),
+                    rows
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut paths: Vec<_> = std::fs::read_dir("wikitext_tables")
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        paths.sort();
+        let mut contents: Vec<String> = paths
+            .iter()
+            .map(|path| std::fs::read_to_string(path).unwrap())
+            .collect();
+        // an empty style before a text with a `|` of its own
+        contents.push("{|\n|+| | {{t|d}}\n||\nx=1|[[b|c]]\n! | a | b\n|}".to_string());
+        // table markup left in the text, written back in `<nowiki>`
+        contents.push("{|\n|+-|}|!!|||-}-\n| a\n</pre>! b\n|}".to_string());
+        contents.push("{|\n| c\n{|\n| d\n|}\n ! e\n|}".to_string());
+        // an unclosed comment, written back with its `<!--` in `<nowiki>`
+        contents.push("{|\n|-\n| x <!-- y\n|}".to_string());
+        contents.push("{| <!--</ref>\n| a\n|}".to_string());
+        for content in contents {
+            let tables = parse_tables(&content);
+            let wikitext = tables_to_wikitext(&tables);
+            let parsed = parse_tables(&wikitext);
+            assert_eq!(normalize(&tables), normalize(&parsed), "{}", content);
+            // the canonical form is written the same again
+            assert_eq!(tables_to_wikitext(&parsed), wikitext, "{}", content);
+        }
+    }
+
+    #[test]
+    fn test_serialize() {
+        let content = "{|class=wikitable\n|+Caption\n! a !! b\n|- style=\"color:red\"\n|align=left| 1 || 2\n|}";
+        let tables = parse_tables(content);
+        assert_eq!(
+            table_to_wikitext(&tables[0]),
+            "{| class=wikitable\n|+ Caption\n|-\n! a\n! b\n|- style=\"color:red\"\n| align=left | 1\n| 2\n|}"
+        );
+
+        // a nested table is written back in its cell
+        let content = std::fs::read_to_string("wikitext_tables/12.txt").unwrap();
+        let parser = parser(&content, false);
+        let wikitext = events_to_wikitext(parser);
+        assert!(wikitext.contains("| style=\"padding:0\" |\n{| class=\"wikitable\"\n|-\n| a1\n"));
+        assert_eq!(parse_tables(&wikitext).len(), 2);
+
+        // with text around the nested table, and two top level tables
+        let content = "{|\n| before\n{|\n| inner\n|}\nafter || x\n|}\ntext\n{|\n| y\n|}";
+        let tables = parse_tables(content);
+        let wikitext = tables_to_wikitext(&tables);
+        assert_eq!(
+            wikitext,
+            "{|\n|-\n| before\n{|\n|-\n| inner\n|}\nafter\n| x\n|}\n\n{|\n|-\n| y\n|}"
+        );
+        assert_eq!(normalize(&parse_tables(&wikitext)), normalize(&tables));
+
+        // the table markup of the text is written in `<nowiki>`
+        let tables = parse_tables("{|\n|+-|}|!!|||-}-\n| c\n{|\n| d\n|}\n ! e\n|}");
+        assert_eq!(
+            tables_to_wikitext(&tables),
+            "{|\n|+ - | }<nowiki>||</nowiki>-}-\n|-\n| c\n{|\n|-\n| d\n|}\n<nowiki>!</nowiki> e\n|}"
+        );
+    }
+
+    #[test]
+    fn test_caption_attrs_round_trip() {
+        let content = "{|\n|+style=\"color:red\"|Styled ''caption''\n| a\n|}";
+        let tables = parse_tables(content);
+        let wikitext = tables_to_wikitext(&tables);
+        assert_eq!(
+            wikitext,
+            "{|\n|+ style=\"color:red\" | Styled ''caption''\n|-\n| a\n|}"
+        );
+        let parsed = parse_tables(&wikitext);
+        assert_eq!(parsed[0].caption_attrs, "style=\"color:red\" |");
+        assert_eq!(normalize(&parsed), normalize(&tables));
+    }
+
+    #[test]
+    fn test_placeholder_in_source() {
+        // only the nested table is written, the placeholders of the source are text
+        let content = "{|\n| <table-ref id=\"0\"/>\n{|\n| <table-ref id=\"0\"/> <table-ref id=\"1\"/>\n|}\n|}";
+        let tables = parse_tables(content);
+        let wikitext = tables_to_wikitext(&tables);
+        assert_eq!(
+            wikitext,
+            "{|\n|-\n| <table-ref id=\"0\"/>\n{|\n|-\n| <table-ref id=\"0\"/> <table-ref id=\"1\"/>\n|}\n|}"
+        );
+        assert_eq!(normalize(&parse_tables(&wikitext)), normalize(&tables));
+    }
+}
+
+#[cfg(test)]
+mod test_cst {
+    use crate::cst::{parse_cst, CstPart};
+    use crate::table::parse_tables;
+
+    fn fixtures() -> Vec<String> {
+        let mut paths: Vec<_> = std::fs::read_dir("wikitext_tables")
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        paths.sort();
+        paths
+            .iter()
+            .map(|path| std::fs::read_to_string(path).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_lossless() {
+        let mut texts = fixtures();
+        texts.extend(
+            [
+                "",
+                "text only\n| not a cell",
+                "{|\n| a || b\n|-\n|}\n{|",
+                "{| class=x\n|+ cap\n! a !! b\n|- style=y\n| 1 || s=1 | 2\n|x\n| in\n{|\n| n\n|}\n|}\nafter\n",
+                "{|\n| <nowiki>\n|}</nowiki> || <!-- \n| -->\n|+ late\n|-\n|-\n!! x\n|}",
+                "\n\n{|\r\n|  a  \r\n|}\r\n",
+            ]
+            .map(String::from),
+        );
+        for text in texts {
+            assert_eq!(parse_cst(&text).to_string(), text);
+        }
+    }
+
+    #[test]
+    fn test_cst_tables() {
+        for text in fixtures() {
+            let tables = parse_tables(&text);
+            let mut cst = parse_cst(&text);
+            for table in &tables {
+                let cst_table = cst.table_mut(table.id).unwrap();
+                assert_eq!(cst_table.rows().count(), table.rows.len());
+                for (row, cst_row) in table.rows.iter().zip(cst_table.rows()) {
+                    assert_eq!(row.cells.len(), cst_row.cells.len());
+                    for (cell, cst_cell) in row.cells.iter().zip(&cst_row.cells) {
+                        assert_eq!(
+                            cell.attrs.trim(),
+                            cst_cell.attrs.as_deref().unwrap_or_default().trim()
+                        );
+                    }
+                }
+            }
+            assert!(cst.table_mut(tables.len()).is_none());
+        }
+    }
+
+    #[test]
+    fn test_edit_cell() {
+        let text = std::fs::read_to_string("wikitext_tables/2.txt").unwrap();
+        let mut cst = parse_cst(&text);
+        let table = cst.table_mut(0).unwrap();
+        let cell = table.cell_mut(1, 2).unwrap();
+        assert_eq!(cell.to_string(), "\n| 足球");
+        cell.set_text("篮球");
+        table.cell_mut(1, 1).unwrap().set_attrs(Some("align=left"));
+        let edited = cst.to_string();
+
+        // only the edited cells are changed
+        let old_cells = "| [[中国足球超级联赛]]（CSL）\n| 足球";
+        let new_cells = "| align=left | [[中国足球超级联赛]]（CSL）\n| 篮球";
+        let start = text.find(old_cells).unwrap();
+        assert_eq!(&edited[..start], &text[..start]);
+        assert_eq!(&edited[start..start + new_cells.len()], new_cells);
+        assert_eq!(
+            &edited[start + new_cells.len()..],
+            &text[start + old_cells.len()..]
+        );
+
+        let tables = cst.to_tables();
+        assert_eq!(tables[0].rows[1].cells[2].text, "篮球");
+        assert_eq!(
+            tables[0].rows[1].cells[1].attributes().get("align"),
+            Some("left")
+        );
+
+        // a nested table is edited by its id
+        let text = std::fs::read_to_string("wikitext_tables/12.txt").unwrap();
+        let mut cst = parse_cst(&text);
+        let nested = cst.table_mut(1).unwrap();
+        nested.cell_mut(0, 1).unwrap().set_text("a2'");
+        assert_eq!(
+            cst.to_string(),
+            text.replace("| a1 || a2\n", "| a1 || a2'\n")
+        );
+        let caption = cst.table_mut(0).unwrap().caption_mut().unwrap();
+        caption.set_text("Outer");
+        assert_eq!(cst.to_tables()[0].caption.as_deref(), Some("Outer"));
+
+        // the table markup of a new text stays in the cell
+        let mut cst = parse_cst("{|\n| a || b\n|}");
+        let table = cst.table_mut(0).unwrap();
+        table.cell_mut(0, 0).unwrap().set_text("c || d\n! e");
+        table.cell_mut(0, 1).unwrap().set_text("x=1 | f");
+        assert_eq!(
+            cst.to_string(),
+            "{|\n| c <nowiki>||</nowiki> d\n<nowiki>!</nowiki> e || | x=1 | f\n|}"
+        );
+        let tables = cst.to_tables();
+        assert_eq!(tables[0].rows[0].cells.len(), 2);
+        assert_eq!(tables[0].rows[0].cells[1].attrs, "|");
+        assert_eq!(tables[0].rows[0].cells[1].text, " x=1 | f");
+    }
+
+    // the text of the parts, with a nested table as its placeholder
+    fn content_text(parts: &[CstPart]) -> String {
+        parts
+            .iter()
+            .map(|part| match part {
+                CstPart::Text(text) => text.clone(),
+                CstPart::Table(_) => "\n<table-ref/>".to_string(),
+            })
+            .collect::<String>()
+            .trim()
+            .to_string()
+    }
+
+    #[test]
+    fn test_cst_matches_parser() {
+        let placeholder = regex::Regex::new(r#"<table-ref id="\d+"/>"#).unwrap();
+        let mut texts = fixtures();
+        texts.extend(
+            [
+                "{|\n| <pre class=\"x\">a\n|b</pre> || c\n|}",
+                "{|\n| <NOWIKI>||</NOWIKI> || d\n! e <!-- \n| --> !! f\n|}",
+                "{|\n|+ s=1 | cap {|\n| in\n|}\n|- a=1\n{|\n| row\n|}\n| x\n|}",
+                "{| a=1\n| b\n|+ late\n|-\n|-\n|| c ||\n! d | e !! f",
+                // a stray closing tag is text, for the parser and the tree
+                "{| </nowiki> -->\n| || g\n|\n| h\n{|\n| i",
+                "x\r\n{|\r\n| j || s=2 | k\r\n|-\r\n!l\r\n|}\r\n",
+            ]
+            .map(String::from),
+        );
+        for text in &texts {
+            let tables = parse_tables(text);
+            let mut cst = parse_cst(text);
+            assert_eq!(&cst.to_string(), text);
+            for table in &tables {
+                let cst_table = cst.table_mut(table.id).unwrap();
+                let caption = cst_table.caption().map(|caption| {
+                    (
+                        caption.attrs.as_deref().unwrap_or_default().trim(),
+                        content_text(&caption.content),
+                    )
+                });
+                assert_eq!(
+                    caption,
+                    table.caption.as_ref().map(|caption| (
+                        table.caption_attrs.trim(),
+                        placeholder
+                            .replace_all(caption.trim(), "<table-ref/>")
+                            .to_string()
+                    ))
+                );
+                assert_eq!(cst_table.rows().count(), table.rows.len());
+                for (row, cst_row) in table.rows.iter().zip(cst_table.rows()) {
+                    assert_eq!(row.cells.len(), cst_row.cells.len());
+                    for (cell, cst_cell) in row.cells.iter().zip(&cst_row.cells) {
+                        assert_eq!(format!("{:?}", cell.kind), format!("{:?}", cst_cell.kind));
+                        assert_eq!(
+                            cell.attrs.trim(),
+                            cst_cell.attrs.as_deref().unwrap_or_default().trim()
+                        );
+                        assert_eq!(
+                            placeholder.replace_all(cell.text.trim(), "<table-ref/>"),
+                            content_text(&cst_cell.content)
+                        );
+                    }
+                }
+            }
+            assert!(cst.table_mut(tables.len()).is_none());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_html {
+    use crate::attributes::parse_attributes;
+    use crate::html::{
+        events_to_html, sanitize_attributes, table_to_html, tables_to_html, HtmlOptions,
+    };
+    use crate::table::parse_tables;
+    use crate::tests::parser;
+
+    #[test]
+    fn test_render_html() {
+        let content = "{| class=\"wikitable\" onclick=\"alert(1)\" style=\"color:red; background:url(javascript:x)\"\n|+ Caption\n! scope=\"col\" colspan=\"2x\" | a <b>\n|- valign=top\n| rowspan=\"2\" data-x=\"1\" | 1 || 2\n|}";
+        let tables = parse_tables(content);
+        assert_eq!(
+            table_to_html(&tables[0], &HtmlOptions::default()),
+            "<table class=\"wikitable\" style=\"color: red;\">\n\
+             <caption>Caption</caption>\n\
+             <tr>\n<th scope=\"col\" colspan=\"2\">a &lt;b&gt;</th>\n</tr>\n\
+             <tr valign=\"top\">\n<td rowspan=\"2\">1</td>\n<td>2</td>\n</tr>\n\
+             </table>"
+        );
+
+        let content = std::fs::read_to_string("wikitext_tables/11.txt").unwrap();
+        let html = tables_to_html(&parse_tables(&content), &HtmlOptions::default());
+        assert!(html.starts_with("<table class=\"wikitable\" style=\"font-size: small;\">\n<tr>\n<th rowspan=\"2\">年份</th>\n<th colspan=\"3\">全部空间</th>\n"));
+    }
+
+    #[test]
+    fn test_sanitize_style() {
+        for (style, expected) in [
+            ("width: expr/**/ession(alert(1))", ""),
+            (
+                "color: red; width: /* x */expression(alert(1))",
+                " style=\"color: red;\"",
+            ),
+            (
+                "background: u/**/rl(x); color: blue /* ; */",
+                " style=\"color: blue;\"",
+            ),
+            ("color: red; /* width: 1px", " style=\"color: red;\""),
+            ("width: \\65 xpression(alert(1))", ""),
+            (
+                "background-image: image-set('https://evil.example/t.png' 1x); color: red",
+                " style=\"color: red;\"",
+            ),
+            ("background: -webkit-image-set('t.png' 1x)", ""),
+            ("background: image ('t.png')", ""),
+            ("color: var(--x); width: 1px", " style=\"width: 1px;\""),
+        ] {
+            let attributes = parse_attributes(&format!("style=\"{}\"", style));
+            assert_eq!(
+                sanitize_attributes("td", &attributes),
+                expected,
+                "{}",
+                style
+            );
+        }
+    }
+
+    #[test]
+    fn test_render_markup() {
+        let content = "{|\n| [[Noble gas|gases]] and [[Xenon]], '''bold ''both''''' [https://example.org site]<br/>''open\n|}";
+        let tables = parse_tables(content);
+        let options = HtmlOptions {
+            render_markup: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            table_to_html(&tables[0], &options),
+            "<table>\n<tr>\n<td>\
+             <a href=\"/wiki/Noble_gas\">gases</a> and <a href=\"/wiki/Xenon\">Xenon</a>, \
+             <b>bold <i>both</i></b> \
+             <a class=\"external\" href=\"https://example.org\">site</a><br><i>open</i>\
+             </td>\n</tr>\n</table>"
+        );
+        // the markup is escaped as it is otherwise
+        let html = table_to_html(&tables[0], &HtmlOptions::default());
+        assert!(html.contains("<td>[[Noble gas|gases]] and [[Xenon]], &#39;&#39;&#39;bold"));
+    }
+
+    #[test]
+    fn test_render_nested_tables() {
+        let content = std::fs::read_to_string("wikitext_tables/12.txt").unwrap();
+        let parser = parser(&content, false);
+        let html = events_to_html(parser, &HtmlOptions::default());
+        assert!(html.contains(
+            "<td style=\"padding: 0;\">\n<table class=\"wikitable\">\n<tr>\n<td>a1</td>\n"
+        ));
+        assert_eq!(html.matches("<table").count(), 2);
+        assert!(html.ends_with(
+            "</table>\n</td>\n</tr>\n<tr>\n<td>Beta</td>\n<td>beta detail</td>\n</tr>\n</table>"
+        ));
+
+        // a placeholder written in the source is escaped as text
+        let content = "{|\n| <table-ref id=\"0\"/>\n{|\n| <table-ref id=\"0\"/>\n|}\n|}";
+        let html = tables_to_html(&parse_tables(content), &HtmlOptions::default());
+        assert_eq!(html.matches("<table>").count(), 2);
+        assert_eq!(
+            html.matches("&lt;table-ref id=&quot;0&quot;/&gt;").count(),
+            2
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_markdown {
+    use crate::markdown::{events_to_markdown, table_to_markdown, MarkdownLinks, MarkdownOptions};
+    use crate::table::parse_tables;
+    use crate::tests::parser;
+
+    #[test]
+    fn test_write_markdown() {
+        let content = "{|\n|+ The [[Noble gas|gases]]\n! rowspan=\"2\" | Name !! colspan=\"2\" | Mass\n|-\n! min !! max\n|-\n| [[Xenon]] || <nowiki>a | b</nowiki>\n|| 131\n|}";
+        let tables = parse_tables(content);
+        assert_eq!(
+            table_to_markdown(&tables[0], &MarkdownOptions::default()),
+            "The gases\n\
+             \n\
+             | Name | Mass min | Mass max |\n\
+             | --- | --- | --- |\n\
+             | Xenon | a \\| b | 131 |"
+        );
+
+        let options = MarkdownOptions {
+            links: MarkdownLinks::Markdown,
+            ..Default::default()
+        };
+        let content = "{|\n| [[Mercury (planet)|Mercury]] || [[:Category:Planets|a [b] c]]\n|-\n| [[Venus]]\n|}";
+        let tables = parse_tables(content);
+        assert_eq!(
+            table_to_markdown(&tables[0], &options),
+            "| [Mercury](/wiki/Mercury_(planet)) | [a \\[b\\] c](/wiki/Category:Planets) |\n\
+             | --- | --- |\n\
+             | [Venus](/wiki/Venus) |  |"
+        );
+    }
+
+    #[test]
+    fn test_write_nested_markdown() {
+        let content = std::fs::read_to_string("wikitext_tables/12.txt").unwrap();
+        let parser = parser(&content, false);
+        let markdown = events_to_markdown(parser, &MarkdownOptions::default());
+        assert!(markdown.starts_with("Outer table\n\n| Name | Detail |\n| --- | --- |\n"));
+        assert!(markdown.ends_with("\n\n| a1 | a2 |\n| --- | --- |\n| a3 | a4 |"));
+    }
+}
+
+#[cfg(test)]
+mod test_clean {
+    use crate::parser::Event;
+    use crate::tests::parser;
+    use crate::utils::{
+        clean_col_text, clean_col_text_with_policy, decode_entities, TemplatePolicy,
+    };
+
+    #[test]
+    fn test_clean_col_text() {
+        let text = " [[Noble gas|gases]] and '''[[Xenon]]'''<ref name=\"a\">{{cite web|url=x}}</ref>, ''a''<br/>b &amp; c&nbsp;&#8211;&#x2013;&bogus; <!-- note --><ref name=\"b\" /> ";
+        assert_eq!(
+            clean_col_text(text),
+            "gases and Xenon, a\nb & c\u{a0}––&bogus;"
+        );
+        assert_eq!(
+            clean_col_text(
+                "[[File:Flag.svg|20px|[[Japan]] flag]] [[Japan]]<span style=\"x\">!</span> [https://example.org site] <nowiki>''[[a]]''</nowiki>"
+            ),
+            "Japan! site ''[[a]]''"
+        );
+        // the placeholder of a nested table is kept
+        assert_eq!(
+            clean_col_text("''x'' <table-ref id=\"1\"/>"),
+            "x <table-ref id=\"1\"/>"
+        );
+        // the pipe trick reads as the title, without its prefix and its suffix
+        assert_eq!(
+            clean_col_text("[[Foo (bar)|]], [[Help:Link|]] and [[w:Star (2001 film)|]]"),
+            "Foo, Link and Star"
+        );
+        // the `|` and `!` left around the text by the table markup
+        assert_eq!(clean_col_text("| a ||"), "a");
+        assert_eq!(
+            clean_col_text_with_policy("!! {{x}} b", TemplatePolicy::Remove),
+            "b"
+        );
+        assert_eq!(decode_entities("&lt;b&gt; &#60; &#xZZ;"), "<b> < &#xZZ;");
+    }
+
+    #[test]
+    fn test_template_policy() {
+        let text = "{{sortname|John|Smith|dab=x}}{{citation needed|date=June 2019}} a {{!}} b {{nowrap|{{small|[[c|d]]}}}} {{open";
+        assert_eq!(
+            clean_col_text_with_policy(text, TemplatePolicy::Keep),
+            text.replace("[[c|d]]", "d")
+        );
+        assert_eq!(clean_col_text_with_policy(text, TemplatePolicy::Remove), "a | b {{open");
+        assert_eq!(
+            clean_col_text_with_policy(text, TemplatePolicy::Arguments),
+            "John Smith a | b d {{open"
+        );
+    }
+
+    #[test]
+    fn test_clean_cell_text() {
+        let content = "{|\n|+ '''caption'''\n| style=\"x\" | [[Oxygen]]<ref>a</ref> || {{sortname|John|Smith}}\n|}";
+        let texts = |clean_cell_text: bool, policy: TemplatePolicy| -> Vec<String> {
+            let mut parser = parser(content, clean_cell_text);
+            parser.template_policy = policy;
+            parser
+                .filter_map(|event| match event {
+                    Event::TableCaption { text, .. }
+                    | Event::ColStyle { text, .. }
+                    | Event::ColEnd { text, .. } => Some(text),
+                    _ => None,
+                })
+                .collect()
+        };
+        // only the text of the cells is cleaned
+        assert_eq!(
+            texts(true, TemplatePolicy::Arguments),
+            vec!["'''caption'''", "style=\"x\" |", "Oxygen", "", "John Smith"]
+        );
+        assert_eq!(
+            texts(false, TemplatePolicy::Arguments),
+            vec![
+                "'''caption'''",
+                "style=\"x\" |",
+                " [[Oxygen]]<ref>a</ref>",
+                "",
+                "{{sortname|John|Smith}}"
+            ]
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_inline {
+    use crate::html::{table_to_html, HtmlOptions};
+    use crate::inline::{parse_inline, Inline, TemplateArg};
+    use crate::markdown::{table_to_markdown, MarkdownOptions};
+    use crate::table::parse_tables;
+    use crate::utils::clean_col_text;
+
+    fn text(text: &str) -> Inline {
+        Inline::Text(text.to_string())
+    }
+
+    fn link(target: &str, label: Option<Vec<Inline>>) -> Inline {
+        Inline::Link {
+            target: target.to_string(),
+            anchor: None,
+            label,
+        }
+    }
+
+    #[test]
+    fn test_links_and_formatting() {
+        let nodes = parse_inline(
+            "[[Noble gas#Properties|the ''gases'']] and '''''[[Xenon]]''''' '''a ''b''' c''",
+        );
+        assert_eq!(
+            nodes,
+            vec![
+                Inline::Link {
+                    target: "Noble gas".to_string(),
+                    anchor: Some("Properties".to_string()),
+                    label: Some(vec![text("the "), Inline::Italic(vec![text("gases")])]),
+                },
+                text(" and "),
+                Inline::Bold(vec![Inline::Italic(vec![link("Xenon", None)])]),
+                text(" "),
+                // the italic is closed with the bold, and opened again after it
+                Inline::Bold(vec![text("a "), Inline::Italic(vec![text("b")])]),
+                Inline::Italic(vec![text(" c")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_templates() {
+        let nodes = parse_inline(
+            "{{sortname|John|Smith|dab=singer}} {{cite web |url=http://x |title=[[A]]}}",
+        );
+        let arg = |name: Option<&str>, value: Vec<Inline>| TemplateArg {
+            name: name.map(String::from),
+            value,
+        };
+        assert_eq!(
+            nodes,
+            vec![
+                Inline::Template {
+                    name: "sortname".to_string(),
+                    args: vec![
+                        arg(None, vec![text("John")]),
+                        arg(None, vec![text("Smith")]),
+                        arg(Some("dab"), vec![text("singer")]),
+                    ],
+                },
+                text(" "),
+                Inline::Template {
+                    name: "cite web".to_string(),
+                    args: vec![
+                        arg(Some("url"), vec![text("http://x ")]),
+                        arg(Some("title"), vec![link("A", None)]),
+                    ],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_elements_and_files() {
+        let nodes = parse_inline("[https://example.org the ''site''] <span style=\"color:red\">red<br/>x</span><ref name=\"a\">{{cite}}</ref><ref name=\"b\" /> <nowiki>''[[a]]''</nowiki><!-- c --> [[File:Flag.svg|20px|border|the [[Japan|flag]]]] <table-ref id=\"2\"/>");
+        assert_eq!(
+            nodes,
+            vec![
+                Inline::ExternalLink {
+                    url: "https://example.org".to_string(),
+                    label: Some(vec![text("the "), Inline::Italic(vec![text("site")])]),
+                },
+                text(" "),
+                Inline::Element {
+                    name: "span".to_string(),
+                    attrs: "style=\"color:red\"".to_string(),
+                    children: vec![
+                        text("red"),
+                        Inline::Element {
+                            name: "br".to_string(),
+                            attrs: String::new(),
+                            children: vec![],
+                        },
+                        text("x"),
+                    ],
+                },
+                Inline::Reference {
+                    name: Some("a".to_string()),
+                    content: vec![Inline::Template {
+                        name: "cite".to_string(),
+                        args: vec![],
+                    }],
+                },
+                Inline::Reference {
+                    name: Some("b".to_string()),
+                    content: vec![],
+                },
+                text(" ''[[a]]'' "),
+                Inline::File {
+                    target: "File:Flag.svg".to_string(),
+                    options: vec!["20px".to_string(), "border".to_string()],
+                    caption: Some(vec![text("the "), link("Japan", Some(vec![text("flag")]))]),
+                },
+                text(" "),
+                Inline::NestedTable(2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unclosed_markup() {
+        // links and templates which are not closed are text
+        assert_eq!(
+            parse_inline("[[a|b '''c {{d|e"),
+            vec![text("[[a|b "), Inline::Bold(vec![text("c {{d|e")])]
+        );
+        let content = "x]] y}} z] </span> a < b > c [not a url] |";
+        assert_eq!(parse_inline(content), vec![text(content)]);
+
+        // the text of a cell without markup is read as it is
+        for i in 1..=12 {
+            let content = std::fs::read_to_string(format!("wikitext_tables/{}.txt", i)).unwrap();
+            for table in parse_tables(&content) {
+                for cell in table.rows.iter().flat_map(|row| &row.cells) {
+                    let nodes = cell.inline();
+                    if !cell.text.is_empty() && !cell.text.contains(['[', '{', '\'', '<', '|']) {
+                        assert_eq!(nodes, vec![text(&cell.text)]);
+                    }
+                }
+            }
+        }
+
+        let tables = parse_tables("{|\n| [[Oxygen]] || 1\n|}");
+        assert_eq!(
+            tables[0].rows[0].cells[0].inline(),
+            vec![link("Oxygen", None)]
+        );
+    }
+
+    #[test]
+    fn test_writers_share_the_parser() {
+        // the writers read the markup the same way as `parse_inline`
+        let content = "{|\n| <nowiki>[[a]]</nowiki> [[b|]] '''c [[d|e]]\n|}";
+        let tables = parse_tables(content);
+        let options = HtmlOptions {
+            render_markup: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            table_to_html(&tables[0], &options),
+            "<table>\n<tr>\n<td>[[a]] <a href=\"/wiki/b\">b</a> \
+             <b>c <a href=\"/wiki/d\">e</a></b></td>\n</tr>\n</table>"
+        );
+        assert_eq!(
+            table_to_markdown(&tables[0], &MarkdownOptions::default()),
+            "| [[a]] b '''c e''' |\n| --- |"
+        );
+        assert_eq!(
+            clean_col_text(&tables[0].rows[0].cells[0].text),
+            "[[a]] b c e"
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_links {
+    use crate::inline::parse_inline;
+    use crate::links::{
+        cell_links, normalize_title, table_links, LinkOptions, LinkTemplate, PageLink,
+    };
+    use crate::table::parse_tables;
+
+    fn page_link(target: &str, title: &str, fragment: Option<&str>, label: &str) -> PageLink {
+        PageLink {
+            target: target.to_string(),
+            title: title.to_string(),
+            fragment: fragment.map(String::from),
+            label: label.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_normalize_title() {
+        assert_eq!(
+            normalize_title(" noble_gas#Physical__properties"),
+            (
+                "Noble gas".to_string(),
+                Some("Physical properties".to_string())
+            )
+        );
+        assert_eq!(
+            normalize_title(":category: chemical_elements"),
+            ("Category:Chemical elements".to_string(), None)
+        );
+        assert_eq!(
+            normalize_title("über:x &amp; y#"),
+            ("Über:x & y".to_string(), None)
+        );
+    }
+
+    #[test]
+    fn test_cell_links() {
+        let nodes = parse_inline("[[noble_gas#Properties|''the'' gases]], [[#Notes]] '''[[xenon]]'''<ref>[[Source]]</ref> {{sortname|John|Smith}} {{nowrap|[[Neon]]}}");
+        assert_eq!(
+            cell_links(&nodes, &LinkOptions::default()),
+            vec![
+                page_link("noble_gas", "Noble gas", Some("Properties"), "the gases"),
+                page_link("xenon", "Xenon", None, "xenon"),
+            ]
+        );
+
+        let options = LinkOptions {
+            template_args: true,
+            link_templates: vec![LinkTemplate::sortname()],
+        };
+        let links = cell_links(&nodes, &options);
+        assert_eq!(links.len(), 4);
+        assert_eq!(
+            links[2],
+            page_link("John Smith", "John Smith", None, "John Smith")
+        );
+        assert_eq!(links[3], page_link("Neon", "Neon", None, "Neon"));
+    }
+
+    #[test]
+    fn test_table_links() {
+        let content = "{|\n! rowspan=\"2\" | [[Hydrogen]] !! colspan=\"2\" | Mass\n|-\n| 1 || [[Oxygen]] and [[Carbon]]\n|}";
+        let tables = parse_tables(content);
+        let links = table_links(&tables[0], &LinkOptions::default());
+        assert_eq!(links.len(), 2);
+        assert_eq!((links[0].row, links[0].cell, links[0].column), (0, 0, 0));
+        // the first column of the second row is taken by the rowspan
+        assert_eq!((links[1].row, links[1].cell, links[1].column), (1, 1, 2));
+        let titles: Vec<&str> = links[1]
+            .links
+            .iter()
+            .map(|link| link.title.as_str())
+            .collect();
+        assert_eq!(titles, vec!["Oxygen", "Carbon"]);
+    }
+}
+
+#[cfg(test)]
+mod test_templates {
+    use crate::inline::{parse_inline, Inline, TemplateArg};
+    use crate::parser::Event;
+    use crate::templates::{
+        expand_templates, BuiltinTemplates, Passthrough, TemplateExpander, TemplateHandler,
+    };
+    use crate::tests::parser;
+
+    #[test]
+    fn test_builtin_templates() {
+        let expander = TemplateExpander::new(BuiltinTemplates);
+        let cases = [
+            ("{{flagicon|USA}} [[United States]]", " [[United States]]"),
+            ("{{flag|Japan}}", "[[Japan]]"),
+            ("{{convert|5|km|mi}}, {{cvt|5|-|10|km}}", "5 km, 5–10 km"),
+            ("{{yes}} {{Yes|Ok}} {{n/a}}", "Yes Ok N/A"),
+            ("{{dts|2001|5|12}}", "May 12, 2001"),
+            // an empty argument keeps the position of the ones after it
+            ("{{dts|2001||12}} {{dts|2001|5|}}", "2001 May 2001"),
+            (
+                "{{dts|2001-05-02|format=dmy}} {{dts|May 2001}}",
+                "2 May 2001 May 2001",
+            ),
+            ("{{sort|Smith|John Smith}}{{hs|01}}", "John Smith"),
+            ("{{sortname|John|Smith}}", "[[John Smith]]"),
+            (
+                "{{sortname|John|Smith|John Smith (actor)}}",
+                "[[John Smith (actor)|John Smith]]",
+            ),
+            (
+                "a{{efn|note}} {{!}} b{{Citation_needed|date=2019}}",
+                "a | b",
+            ),
+            // the templates of the arguments are expanded first
+            ("{{nowrap|{{convert|5|km}}}}", "5 km"),
+            ("{{nowrap|{{cite web|url=x}}}}", "{{cite web|url=x}}"),
+            (
+                "{{cite web|url=x}} <nowiki>{{yes}}</nowiki> {{yes",
+                "{{cite web|url=x}} <nowiki>{{yes}}</nowiki> {{yes",
+            ),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(expander.expand_text(text), expected, "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_template_handler() {
+        let medal = |name: &str, _: &[TemplateArg]| match name {
+            "gold1" => Some(vec![Inline::Text("1st".to_string())]),
+            _ => None,
+        };
+        let nodes = parse_inline("{{gold1}} ''{{yes}}''");
+        assert_eq!(expand_templates(&nodes, &Passthrough), nodes);
+        assert_eq!(
+            expand_templates(&nodes, &medal),
+            vec![
+                Inline::Text("1st ".to_string()),
+                Inline::Italic(parse_inline("{{yes}}"))
+            ]
+        );
+        let handler = (medal, BuiltinTemplates);
+        assert_eq!(handler.expand("yes", &[]), Some(parse_inline("Yes")));
+        assert_eq!(
+            TemplateExpander::new(handler).expand_text("{{gold1}} ''{{yes}}''"),
+            "1st ''Yes''"
+        );
+    }
+
+    #[test]
+    fn test_parser_template_handler() {
+        let content =
+            "{|\n| {{flagicon|JPN}} {{flag|Japan}} || {{convert|5|km}} || {{cite web|url=x}}\n|}";
+        let texts = |clean_cell_text: bool| -> Vec<String> {
+            let mut parser = parser(content, clean_cell_text);
+            parser.set_template_handler(BuiltinTemplates);
+            parser
+                .filter_map(|event| match event {
+                    Event::ColEnd { text, .. } => Some(text),
+                    _ => None,
+                })
+                .collect()
+        };
+        assert_eq!(texts(true), vec!["Japan", "5 km", "{{cite web|url=x}}"]);
+        assert_eq!(
+            texts(false),
+            vec![" [[Japan]]", "5 km", "{{cite web|url=x}}"]
+        );
+    }
+}
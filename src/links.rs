@@ -0,0 +1,251 @@
+use crate::grid::Grid;
+use crate::inline::{plain_text, Inline, InlineParser, TemplateArg};
+use crate::parser::Event;
+use crate::table::{tables_from_events, Table};
+use crate::utils::decode_entities;
+
+// Find the links of the cells, with the titles of the pages they link to
+// normalized as MediaWiki does, e.g. for entity linking.
+// https://www.mediawiki.org/wiki/Manual:Page_title
+
+// the namespaces whose name is normalized, the others are a part of the title
+const NAMESPACES: [&str; 12] = [
+    "Talk",
+    "User",
+    "Wikipedia",
+    "File",
+    "Image",
+    "MediaWiki",
+    "Template",
+    "Help",
+    "Category",
+    "Portal",
+    "Draft",
+    "Module",
+];
+
+/// A template which links to a page, the title is its positional
+/// arguments at `args` (1-based) joined by a space.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkTemplate {
+    pub name: String,
+    pub args: Vec<usize>,
+}
+
+impl LinkTemplate {
+    /// `{{sortname|First|Last}}`, a link to `First Last`.
+    pub fn sortname() -> Self {
+        LinkTemplate {
+            name: "sortname".to_string(),
+            args: vec![1, 2],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LinkOptions {
+    /// Also find the links in the arguments of templates.
+    pub template_args: bool,
+    /// The templates which are read as a link.
+    pub link_templates: Vec<LinkTemplate>,
+}
+
+/// A link to a page.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PageLink {
+    // as it is written, e.g. `noble_gas`
+    pub target: String,
+    // normalized, e.g. `Noble gas`
+    pub title: String,
+    pub fragment: Option<String>,
+    // the text of the link as it reads on the page
+    pub label: String,
+}
+
+/// The links of a cell, `table.rows[row].cells[cell]`, which starts at
+/// the `column` of the grid of the table.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CellLinks {
+    // the id of the table
+    pub table: usize,
+    pub row: usize,
+    pub cell: usize,
+    pub column: usize,
+    pub links: Vec<PageLink>,
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// The title of the page a link target links to: the HTML entities
+/// decoded, `_` read as a space, the first letter capitalized, and the name
+/// of the namespace as it is defined. The fragment after `#` is split off.
+pub fn normalize_title(target: &str) -> (String, Option<String>) {
+    let target = decode_entities(target).replace('_', " ");
+    let (title, fragment) = match target.split_once('#') {
+        Some((title, fragment)) => (title, Some(fragment)),
+        None => (target.as_str(), None),
+    };
+    let collapse = |text: &str| text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let title = collapse(title.trim_start().trim_start_matches(':'));
+    let fragment = fragment
+        .map(collapse)
+        .filter(|fragment| !fragment.is_empty());
+
+    let title = match title.split_once(':') {
+        Some((namespace, rest)) => {
+            match NAMESPACES
+                .iter()
+                .find(|ns| ns.eq_ignore_ascii_case(namespace.trim()))
+            {
+                Some(namespace) => format!("{}:{}", namespace, capitalize(rest.trim())),
+                None => capitalize(&title),
+            }
+        }
+        None => capitalize(&title),
+    };
+    (title, fragment)
+}
+
+fn template_link(name: &str, args: &[TemplateArg], options: &LinkOptions) -> Option<PageLink> {
+    let name = normalize_title(name).0;
+    let link_template = options
+        .link_templates
+        .iter()
+        .find(|template| normalize_title(&template.name).0 == name)?;
+    let positional: Vec<String> = args
+        .iter()
+        .filter(|arg| arg.name.is_none())
+        .map(|arg| plain_text(&arg.value).trim().to_string())
+        .collect();
+    let target = link_template
+        .args
+        .iter()
+        .filter_map(|&i| positional.get(i.checked_sub(1)?))
+        .filter(|arg| !arg.is_empty())
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(" ");
+    if target.is_empty() {
+        return None;
+    }
+    let (title, fragment) = normalize_title(&target);
+    Some(PageLink {
+        label: target.clone(),
+        target,
+        title,
+        fragment,
+    })
+}
+
+fn find_links(nodes: &[Inline], options: &LinkOptions, links: &mut Vec<PageLink>) {
+    for node in nodes {
+        match node {
+            Inline::Link { target, anchor, .. } => {
+                let (title, _) = normalize_title(target);
+                // a link to a section of the same page
+                if title.is_empty() {
+                    continue;
+                }
+                let fragment = anchor
+                    .as_deref()
+                    .and_then(|anchor| normalize_title(&format!("#{}", anchor)).1);
+                links.push(PageLink {
+                    target: target.clone(),
+                    title,
+                    fragment,
+                    label: plain_text(std::slice::from_ref(node)),
+                });
+            }
+            Inline::Template { name, args } => {
+                if let Some(link) = template_link(name, args, options) {
+                    links.push(link);
+                }
+                if options.template_args {
+                    for arg in args {
+                        find_links(&arg.value, options, links);
+                    }
+                }
+            }
+            Inline::Bold(children)
+            | Inline::Italic(children)
+            | Inline::Element { children, .. }
+            | Inline::ExternalLink {
+                label: Some(children),
+                ..
+            }
+            | Inline::File {
+                caption: Some(children),
+                ..
+            } => find_links(children, options, links),
+            // a reference is not the text of the cell
+            Inline::Reference { .. } => {}
+            Inline::Text(_)
+            | Inline::ExternalLink { .. }
+            | Inline::File { .. }
+            | Inline::NestedTable(_) => {}
+        }
+    }
+}
+
+/// The links of the nodes, in the order they are in the text.
+pub fn cell_links(nodes: &[Inline], options: &LinkOptions) -> Vec<PageLink> {
+    let mut links = Vec::new();
+    find_links(nodes, options, &mut links);
+    links
+}
+
+fn find_table_links(
+    table: &Table,
+    parser: &InlineParser,
+    options: &LinkOptions,
+    out: &mut Vec<CellLinks>,
+) {
+    let grid = Grid::from_table(table);
+    for (row_idx, row) in table.rows.iter().enumerate() {
+        for (cell_idx, cell) in row.cells.iter().enumerate() {
+            let links = cell_links(&parser.parse(&cell.text), options);
+            if links.is_empty() {
+                continue;
+            }
+            let column = grid.slots[row_idx]
+                .iter()
+                .position(|slot| slot.is_some_and(|slot| slot.is_origin && slot.cell == cell_idx))
+                .unwrap_or(cell_idx);
+            out.push(CellLinks {
+                table: table.id,
+                row: row_idx,
+                cell: cell_idx,
+                column,
+                links,
+            });
+        }
+    }
+}
+
+/// The links of each cell of the table which has any.
+pub fn table_links(table: &Table, options: &LinkOptions) -> Vec<CellLinks> {
+    let mut out = Vec::new();
+    find_table_links(table, &InlineParser::default(), options, &mut out);
+    out
+}
+
+/// The links of each cell of every table of the events.
+pub fn events_links<I: IntoIterator<Item = Event>>(
+    events: I,
+    options: &LinkOptions,
+) -> Vec<CellLinks> {
+    let parser = InlineParser::default();
+    let mut out = Vec::new();
+    for table in tables_from_events(events) {
+        find_table_links(&table, &parser, options, &mut out);
+    }
+    out
+}
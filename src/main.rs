@@ -1,13 +1,159 @@
 use std::env;
 use std::fs::File;
-use std::io::Read;
+use std::io::{self, Read};
+use wikitext_table_parser::export::{write_events, CsvFormat, CsvOptions};
+use wikitext_table_parser::html::{events_to_html, HtmlOptions};
+use wikitext_table_parser::links::{events_links, LinkOptions, LinkTemplate};
+use wikitext_table_parser::markdown::{events_to_markdown, MarkdownOptions};
 use wikitext_table_parser::parser::{Event, WikitextTableParser};
 use wikitext_table_parser::tokenizer::{
     get_all_cell_text_special_tokens, get_all_table_special_tokens, Tokenizer,
 };
 
+// Parse the tables of the pages of a dump, one line for each page.
+// With `--jsonl` (the `serde` feature), one line of JSON for each table.
+//     --dump <path> [--threads N] [--ordered] [--strict] [--articles] [--jsonl]
+#[cfg(feature = "dump")]
+fn run_dump(args: &[String]) {
+    use wikitext_table_parser::batch::{parse_batch, BatchOptions};
+    use wikitext_table_parser::dump::open_dump;
+    #[cfg(feature = "serde")]
+    use wikitext_table_parser::jsonl::{JsonlWriter, PageMetadata};
+
+    let mut options = BatchOptions::default();
+    let mut articles = false;
+    let mut jsonl = false;
+    let mut path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--threads" => options.threads = args.next().and_then(|n| n.parse().ok()),
+            "--ordered" => options.ordered = true,
+            "--strict" => options.strict = true,
+            "--articles" => articles = true,
+            "--jsonl" => jsonl = true,
+            _ => path = Some(arg.clone()),
+        }
+    }
+    let path = match path {
+        Some(path) => path,
+        None => {
+            eprintln!("The path of the dump is missing.");
+            return;
+        }
+    };
+    #[cfg(not(feature = "serde"))]
+    if jsonl {
+        eprintln!("Writing JSON Lines needs the `serde` feature.");
+        return;
+    }
+
+    let mut reader = match open_dump(&path) {
+        Ok(reader) => reader,
+        Err(err) => {
+            eprintln!("Error opening the dump: {}", err);
+            return;
+        }
+    };
+    if articles {
+        reader.skip_redirects = true;
+        reader.namespaces = Some(vec![0]);
+    }
+    let documents = reader.documents().map_while(|document| match document {
+        Ok(document) => Some(document),
+        Err(err) => {
+            eprintln!("Error reading the dump: {}", err);
+            None
+        }
+    });
+    #[cfg(feature = "serde")]
+    let mut jsonl = jsonl.then(|| JsonlWriter::new(io::stdout().lock()));
+    let stats = parse_batch(documents, &options, |output| match output.result {
+        #[cfg(feature = "serde")]
+        Ok(tables) if jsonl.is_some() => {
+            let page = PageMetadata {
+                page_id: Some(output.id),
+                title: Some(output.title),
+                namespace: output.namespace,
+                revision_id: output.revision_id,
+            };
+            if let Some(Err(err)) = jsonl.as_mut().map(|w| w.write_tables(&page, &tables)) {
+                eprintln!("Error writing the tables: {}", err);
+            }
+        }
+        Ok(tables) => println!("{}\t{}\t{} tables", output.id, output.title, tables.len()),
+        Err(err) => eprintln!("{}\t{}\tfailed: {}", output.id, output.title, err),
+    });
+    eprintln!("{}", stats);
+}
+
+// Print the tables of one page of a multistream dump, found by its index.
+//     --page <dump> <index> <title> [--id]
+#[cfg(feature = "dump")]
+fn run_page(args: &[String]) {
+    use wikitext_table_parser::multistream::{find_page, PageKey};
+
+    let by_id = args.iter().any(|arg| arg == "--id");
+    let args: Vec<&String> = args.iter().filter(|arg| *arg != "--id").collect();
+    let (dump_path, index_path, key) = match args.as_slice() {
+        [dump_path, index_path, key] => (dump_path, index_path, key.as_str()),
+        _ => {
+            eprintln!("Usage: --page <dump> <index> <title> [--id]");
+            return;
+        }
+    };
+    let key = if by_id {
+        match key.parse() {
+            Ok(id) => PageKey::Id(id),
+            Err(_) => {
+                eprintln!("Not a page id: {}", key);
+                return;
+            }
+        }
+    } else {
+        PageKey::Title(key)
+    };
+
+    let page = match find_page(dump_path, index_path, key) {
+        Ok(Some(page)) => page,
+        Ok(None) => {
+            eprintln!("The page is not in the index.");
+            return;
+        }
+        Err(err) => {
+            eprintln!("Error reading the dump: {}", err);
+            return;
+        }
+    };
+    println!(
+        "{}\t{}\t{} tables",
+        page.page_id,
+        page.title,
+        page.tables.len()
+    );
+    for table in page.tables {
+        println!("Table {:?}", table.caption);
+        for row in table.rows {
+            let cells: Vec<&str> = row.cells.iter().map(|cell| cell.text.as_str()).collect();
+            println!("{:?}", cells);
+        }
+    }
+}
+
 fn main() {
     let args: Vec<String> = env::args().collect();
+    let mode = args.get(1).map(String::as_str);
+    if mode == Some("--dump") || mode == Some("--page") {
+        #[cfg(feature = "dump")]
+        if mode == Some("--dump") {
+            run_dump(&args[2..]);
+        } else {
+            run_page(&args[2..]);
+        }
+        #[cfg(not(feature = "dump"))]
+        eprintln!("Reading a dump needs the `dump` feature.");
+        return;
+    }
     let file_path = args[1].clone();
 
     // Attempt to open the file
@@ -21,39 +167,112 @@ fn main() {
 
     // Read the contents of the file into a String
     let mut content = String::new();
-    if let Err(_) = file.read_to_string(&mut content) {
+    if file.read_to_string(&mut content).is_err() {
         eprintln!("Error reading the file into a string.");
         return;
     }
+
+    // <path> --csv | --tsv | --html | --markdown | --links, write the tables (or their
+    // links) instead of the events. With `--raw` the text of the cells is not
+    // cleaned, the writers render the markup of the cells themselves anyway.
+    let mut writer = None;
+    let mut raw = false;
+    for arg in &args[2..] {
+        match arg.as_str() {
+            "--csv" | "--tsv" | "--html" | "--markdown" | "--links" => writer = Some(arg.as_str()),
+            "--raw" => raw = true,
+            _ => {}
+        }
+    }
     let table_tokenizer = Tokenizer::build(get_all_table_special_tokens());
     let cell_tokenizer = Tokenizer::build(get_all_cell_text_special_tokens());
+    let clean_cell_text = writer.is_none() && !raw;
     let wikitext_table_parser =
-        WikitextTableParser::new(table_tokenizer, cell_tokenizer, &content, true);
+        WikitextTableParser::new(table_tokenizer, cell_tokenizer, &content, clean_cell_text);
+
+    if writer == Some("--html") {
+        let options = HtmlOptions {
+            render_markup: true,
+            ..Default::default()
+        };
+        println!("{}", events_to_html(wikitext_table_parser, &options));
+        return;
+    }
+    if writer == Some("--markdown") {
+        let markdown = events_to_markdown(wikitext_table_parser, &MarkdownOptions::default());
+        println!("{}", markdown);
+        return;
+    }
+    // one line for each link: table, row, column, title and label
+    if writer == Some("--links") {
+        let options = LinkOptions {
+            template_args: true,
+            link_templates: vec![LinkTemplate::sortname()],
+        };
+        for cell in events_links(wikitext_table_parser, &options) {
+            for link in cell.links {
+                let title = match link.fragment {
+                    Some(fragment) => format!("{}#{}", link.title, fragment),
+                    None => link.title,
+                };
+                println!(
+                    "{}\t{}\t{}\t{}\t{}",
+                    cell.table,
+                    cell.row,
+                    cell.column,
+                    title,
+                    link.label.replace(['\t', '\n'], " ")
+                );
+            }
+        }
+        return;
+    }
+    let format = match writer {
+        Some("--csv") => Some(CsvFormat::Csv),
+        Some("--tsv") => Some(CsvFormat::Tsv),
+        _ => None,
+    };
+    if let Some(format) = format {
+        let options = CsvOptions {
+            format,
+            ..Default::default()
+        };
+        if let Err(err) = write_events(&mut io::stdout().lock(), wikitext_table_parser, &options) {
+            eprintln!("Error writing the tables: {}", err);
+        }
+        return;
+    }
     for event in wikitext_table_parser {
         match event {
-            Event::TableStart {} => {
-                println!("Table START!");
+            Event::TableStart { depth, .. } => {
+                println!("Table START! (depth {})", depth);
             }
-            Event::TableStyle { text: table_style } => {
+            Event::TableStyle {
+                text: table_style, ..
+            } => {
                 println!("table style{:?}#", table_style);
             }
-            Event::TableCaption { text } => {
+            Event::TableCaption { text, .. } => {
                 println!("table name{:?}#", text);
             }
-            Event::RowStyle { text: row_style } => {
+            Event::RowStyle {
+                text: row_style, ..
+            } => {
                 println!("----- {:?} -----", row_style);
             }
-            Event::ColStart { cell_type } =>{
+            Event::ColStart { cell_type, .. } =>{
                 print!("{:?} ",cell_type);
             }
-            Event::ColStyle { text: col_style } => {
+            Event::ColStyle {
+                text: col_style, ..
+            } => {
                 print!("style: {:?} -> ", col_style);
             }
-            Event::ColEnd { text } => {
+            Event::ColEnd { text, .. } => {
                 println!("data: {:?}", text);
             }
-            Event::TableEnd {} => {
-                println!("Table END!");
+            Event::TableEnd { depth, .. } => {
+                println!("Table END! (depth {})", depth);
             }
             _ => {}
         }
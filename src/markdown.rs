@@ -0,0 +1,152 @@
+use crate::export::{table_records, CellCleaning, CsvOptions, HeaderRows, SpanFill};
+use crate::html::link_href;
+use crate::inline::{
+    link_label, link_target_text, link_text, parse_inline, write_nodes, Inline, InlineWriter,
+};
+use crate::parser::Event;
+use crate::table::{tables_from_events, Table};
+
+// Write the tables as GitHub flavored Markdown, e.g. for the docs or a
+// prompt. A Markdown table has no spans and no nested tables, so the spans
+// are flattened as in the CSV export and the nested tables are written
+// after the table they are in.
+// https://github.github.com/gfm/#tables-extension-
+
+/// How the `[[links]]` of the text are written.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum MarkdownLinks {
+    // the label of the link, e.g. `gases` for `[[Noble gas|gases]]`
+    #[default]
+    Text,
+    // a link to the page, e.g. `[gases](/wiki/Noble_gas)`
+    Markdown,
+}
+
+#[derive(Debug, Clone)]
+pub struct MarkdownOptions {
+    pub links: MarkdownLinks,
+    /// Prepended to the title of a link written as `MarkdownLinks::Markdown`.
+    pub link_prefix: String,
+    pub span_fill: SpanFill,
+}
+
+impl Default for MarkdownOptions {
+    fn default() -> Self {
+        MarkdownOptions {
+            links: MarkdownLinks::default(),
+            link_prefix: "/wiki/".to_string(),
+            span_fill: SpanFill::default(),
+        }
+    }
+}
+
+// Writes the links of the nodes as `MarkdownOptions::links`, and the rest
+// back as wikitext. In a cell the `|` would end the cell, so it is escaped.
+struct LinkWriter<'a> {
+    options: &'a MarkdownOptions,
+    in_cell: bool,
+}
+
+impl InlineWriter for LinkWriter<'_> {
+    fn write_node(&self, out: &mut String, node: &Inline) -> bool {
+        let (target, anchor, label) = match node {
+            Inline::Link {
+                target,
+                anchor,
+                label,
+            } => (target, anchor, label),
+            // a file is not a link, and has no text of its own
+            Inline::File { .. } => return true,
+            _ => return false,
+        };
+        let mut text = String::new();
+        match link_label(label) {
+            Some(label) => write_nodes(&mut text, label, self),
+            None => self.write_text(&mut text, &link_text(target, anchor, label)),
+        }
+        match self.options.links {
+            MarkdownLinks::Text => *out += &text,
+            MarkdownLinks::Markdown => {
+                *out += &format!(
+                    "[{}]({})",
+                    text.replace('[', "\\[").replace(']', "\\]"),
+                    link_href(&self.options.link_prefix, &link_target_text(target, anchor))
+                )
+            }
+        }
+        true
+    }
+
+    fn write_text(&self, out: &mut String, text: &str) {
+        match self.in_cell {
+            true => *out += &text.replace('|', "\\|"),
+            false => *out += text,
+        }
+    }
+}
+
+// the text with its links converted
+fn convert_text(text: &str, options: &MarkdownOptions, in_cell: bool) -> String {
+    let mut out = String::new();
+    write_nodes(
+        &mut out,
+        &parse_inline(text),
+        &LinkWriter { options, in_cell },
+    );
+    out
+}
+
+/// Write one table, the caption as a line before it. The header rows of
+/// the table are merged into the header row of the Markdown table, or the
+/// first row is the header if the table has none.
+pub fn table_to_markdown(table: &Table, options: &MarkdownOptions) -> String {
+    let csv_options = CsvOptions {
+        header_rows: HeaderRows::Merge,
+        span_fill: options.span_fill,
+        // a row of a Markdown table is one line
+        cleaning: CellCleaning::Collapse,
+        ..Default::default()
+    };
+    let mut lines = Vec::new();
+    if let Some(caption) = &table.caption {
+        let caption = convert_text(&CellCleaning::Collapse.clean(caption), options, false);
+        if !caption.is_empty() {
+            // an empty line, or the caption would be read as the header row
+            lines.push(caption);
+            lines.push(String::new());
+        }
+    }
+    let records = table_records(table, &csv_options);
+    let line = |cells: Vec<String>| format!("| {} |", cells.join(" | "));
+    for (i, record) in records.iter().enumerate() {
+        lines.push(line(
+            record
+                .iter()
+                .map(|text| convert_text(text, options, true))
+                .collect(),
+        ));
+        if i == 0 {
+            lines.push(line(vec!["---".to_string(); record.len()]));
+        }
+    }
+    lines.join("\n")
+}
+
+/// Write every table, including the nested tables, in the order they
+/// start. The tables are separated by an empty line.
+pub fn tables_to_markdown(tables: &[Table], options: &MarkdownOptions) -> String {
+    tables
+        .iter()
+        .map(|table| table_to_markdown(table, options))
+        .filter(|markdown| !markdown.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Write the tables of the events.
+pub fn events_to_markdown<I: IntoIterator<Item = Event>>(
+    events: I,
+    options: &MarkdownOptions,
+) -> String {
+    tables_to_markdown(&tables_from_events(events), options)
+}
@@ -0,0 +1,199 @@
+use crate::dump::{open_maybe_bz2, DumpError, DumpPage, DumpReader};
+use bzip2::read::BzDecoder;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+// Read a page of `pages-articles-multistream.xml.bz2` by its index
+// `pages-articles-multistream-index.txt.bz2`. The dump is made of bz2
+// streams of about 100 pages, and each line of the index is
+// `offset:page_id:title`, the offset of the stream the page is in.
+// https://meta.wikimedia.org/wiki/Data_dumps/Dump_format
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexEntry {
+    // the byte offset of the bz2 stream in the dump
+    pub offset: u64,
+    pub page_id: u64,
+    pub title: String,
+}
+
+/// A page to find in the index.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PageKey<'a> {
+    Id(u64),
+    Title(&'a str),
+}
+
+impl IndexEntry {
+    pub fn matches(&self, key: PageKey) -> bool {
+        match key {
+            PageKey::Id(id) => self.page_id == id,
+            PageKey::Title(title) => self.title == title,
+        }
+    }
+}
+
+fn parse_index_line(line: &str) -> Option<IndexEntry> {
+    // a title may have `:` in it, e.g. `Template:Infobox`
+    let mut fields = line.splitn(3, ':');
+    let offset = fields.next()?.parse().ok()?;
+    let page_id = fields.next()?.parse().ok()?;
+    let title = fields.next()?.to_string();
+    Some(IndexEntry {
+        offset,
+        page_id,
+        title,
+    })
+}
+
+/// The entries of an index, read line by line.
+pub struct IndexReader<R: BufRead> {
+    reader: R,
+    line: String,
+}
+
+/// Open an index file, plain or `.bz2`.
+pub fn open_index<P: AsRef<Path>>(path: P) -> io::Result<IndexReader<Box<dyn BufRead + Send>>> {
+    Ok(IndexReader::new(open_maybe_bz2(path)?))
+}
+
+impl<R: BufRead> IndexReader<R> {
+    pub fn new(reader: R) -> Self {
+        IndexReader {
+            reader,
+            line: String::new(),
+        }
+    }
+
+    /// Find the first entry of the page. The index is read up to it, so
+    /// this is for a one-off lookup, build a `PageIndex` to find many pages.
+    pub fn find(&mut self, key: PageKey) -> io::Result<Option<IndexEntry>> {
+        for entry in self.by_ref() {
+            let entry = entry?;
+            if entry.matches(key) {
+                return Ok(Some(entry));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<R: BufRead> Iterator for IndexReader<R> {
+    type Item = io::Result<IndexEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.line.clear();
+            match self.reader.read_line(&mut self.line) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(err) => return Some(Err(err)),
+            }
+            let line = self.line.trim_end_matches(['\r', '\n']);
+            if line.is_empty() {
+                continue;
+            }
+            return Some(parse_index_line(line).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("not an index line: {:?}", line),
+                )
+            }));
+        }
+    }
+}
+
+/// The entries of an index read once, to find many pages by title or id
+/// without reading the index again.
+#[derive(Debug, Clone, Default)]
+pub struct PageIndex {
+    entries: Vec<IndexEntry>,
+    // the position of the first entry of each title and id in `entries`
+    by_title: HashMap<String, usize>,
+    by_id: HashMap<u64, usize>,
+}
+
+impl PageIndex {
+    /// Read all of the entries of the index.
+    pub fn read<R: BufRead>(reader: IndexReader<R>) -> io::Result<Self> {
+        let mut index = PageIndex::default();
+        for entry in reader {
+            index.push(entry?);
+        }
+        Ok(index)
+    }
+
+    /// Open and read an index file, plain or `.bz2`.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        PageIndex::read(open_index(path)?)
+    }
+
+    fn push(&mut self, entry: IndexEntry) {
+        let position = self.entries.len();
+        self.by_title.entry(entry.title.clone()).or_insert(position);
+        self.by_id.entry(entry.page_id).or_insert(position);
+        self.entries.push(entry);
+    }
+
+    /// The first entry of the page, same as `IndexReader::find`.
+    pub fn get(&self, key: PageKey) -> Option<&IndexEntry> {
+        let position = match key {
+            PageKey::Id(id) => self.by_id.get(&id),
+            PageKey::Title(title) => self.by_title.get(title),
+        };
+        position.map(|&position| &self.entries[position])
+    }
+
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// A multistream dump, read one stream at a time.
+pub struct MultistreamDump<R: Read + Seek> {
+    reader: R,
+}
+
+impl MultistreamDump<File> {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(MultistreamDump::new(File::open(path)?))
+    }
+}
+
+impl<R: Read + Seek> MultistreamDump<R> {
+    pub fn new(reader: R) -> Self {
+        MultistreamDump { reader }
+    }
+
+    /// Read the page of the index entry, only the stream it is in is
+    /// decompressed. None if the page is not in the stream.
+    pub fn read_page(&mut self, entry: &IndexEntry) -> Result<Option<DumpPage>, DumpError> {
+        self.reader.seek(SeekFrom::Start(entry.offset))?;
+        // `BzDecoder` stops at the end of the stream
+        let stream = BufReader::new(BzDecoder::new(&mut self.reader));
+        DumpReader::new(stream).find_page(entry.page_id)
+    }
+}
+
+/// Find the page in the index and read it from the dump.
+pub fn find_page<P: AsRef<Path>, Q: AsRef<Path>>(
+    dump_path: P,
+    index_path: Q,
+    key: PageKey,
+) -> Result<Option<DumpPage>, DumpError> {
+    let entry = match open_index(index_path)?.find(key)? {
+        Some(entry) => entry,
+        None => return Ok(None),
+    };
+    MultistreamDump::open(dump_path)?.read_page(&entry)
+}
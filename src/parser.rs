@@ -1,8 +1,17 @@
+use crate::diagnostics::{Diagnostic, DiagnosticCode, ParseError, Severity};
+use crate::document::{protected_regions, strip_opaque_spans};
+use crate::inline::InlineParser;
+use crate::templates::{BuiltinTemplates, TemplateExpander, TemplateHandler};
 use crate::tokenizer::CellTextSpecialTokens;
 use crate::tokenizer::TableSpecialTokens;
-use crate::tokenizer::Tokenizer;
+use crate::tokenizer::{Token, Tokenizer, TypedTokenizer};
+use crate::utils::{clean_col_text_with_policy, TemplatePolicy};
 use pyo3::prelude::*;
-use std::str::FromStr;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{BufRead, ErrorKind};
+use std::ops::Range;
+use std::sync::Arc;
 
 // https://en.wikiversity.org/wiki/Help:Wikitext_quick_reference
 
@@ -15,56 +24,886 @@ pub enum State {
     ReadRow,
 }
 
+/// A location in the parsed text. `start` and `end` are byte offsets,
+/// `line` and `column` (1-based, column counted in chars) point at `start`.
+#[pyclass]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    #[pyo3(get)]
+    pub start: usize,
+    #[pyo3(get)]
+    pub end: usize,
+    #[pyo3(get)]
+    pub line: usize,
+    #[pyo3(get)]
+    pub column: usize,
+}
+
 #[pyclass]
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Event {
-    TableStart {},
-    TableStyle { text: String },
-    TableEnd {},
-    ColStart { cell_type: CellType },
-    ColStyle { text: String },
-    ColEnd { text: String },
-    TableCaptionStart {},
-    TableCaption { text: String },
-    RowStart {},
-    RowStyle { text: String },
-    RowEnd {},
+    // `id` numbers the tables in the order they start, `depth` is 0 for a
+    // top level table and grows by one for each enclosing table.
+    TableStart { id: usize, depth: usize, span: Span },
+    TableStyle { text: String, span: Span },
+    TableEnd { id: usize, depth: usize, span: Span },
+    ColStart { cell_type: CellType, span: Span },
+    ColStyle { text: String, span: Span },
+    ColEnd { text: String, span: Span },
+    TableCaptionStart { span: Span },
+    TableCaptionStyle { text: String, span: Span },
+    TableCaption { text: String, span: Span },
+    RowStart { span: Span },
+    RowStyle { text: String, span: Span },
+    RowEnd { span: Span },
+}
+
+impl Event {
+    pub fn span(&self) -> Span {
+        match self {
+            Event::TableStart { span, .. }
+            | Event::TableStyle { span, .. }
+            | Event::TableEnd { span, .. }
+            | Event::ColStart { span, .. }
+            | Event::ColStyle { span, .. }
+            | Event::ColEnd { span, .. }
+            | Event::TableCaptionStart { span }
+            | Event::TableCaptionStyle { span, .. }
+            | Event::TableCaption { span, .. }
+            | Event::RowStart { span }
+            | Event::RowStyle { span, .. }
+            | Event::RowEnd { span } => *span,
+        }
+    }
 }
 
 #[pyclass]
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CellType {
     HeaderCell,
     DataCell,
 }
 
+/// The text left in the enclosing cell where a nested table was found.
+#[pyfunction]
+pub fn nested_table_placeholder(id: usize) -> String {
+    format!("<table-ref id=\"{}\"/>", id)
+}
+
+// the id and the byte range of the first placeholder of a nested table
+pub(crate) fn find_nested_table_placeholder(text: &str) -> Option<(usize, usize, usize)> {
+    let prefix = "<table-ref id=\"";
+    let mut offset = 0;
+    while let Some(found) = text[offset..].find(prefix) {
+        let start = offset + found;
+        let digits = &text[start + prefix.len()..];
+        let len = digits
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(digits.len());
+        if let Ok(id) = digits[..len].parse() {
+            if digits[len..].starts_with("\"/>") {
+                return Some((id, start, start + prefix.len() + len + 3));
+            }
+        }
+        offset = start + prefix.len();
+    }
+    None
+}
+
+// The length of the cell style, up to and including the first `|`
+// which is not in a link or template, 0 if the cell has no style.
+pub(crate) fn cell_style_len(
+    cell_tokenizer: &TypedTokenizer<CellTextSpecialTokens>,
+    cell_text: &str,
+) -> usize {
+    let regions = protected_regions(cell_text);
+    let mut style_len = 0;
+    let mut in_closure = false;
+    for token in cell_tokenizer.tokens(cell_text) {
+        let token_start = style_len;
+        style_len += token.as_str().len();
+        // e.g. the `|` of `<nowiki>|</nowiki>`
+        if regions.iter().any(|region| region.contains(&token_start)) {
+            continue;
+        }
+        match token {
+            Token::Special(CellTextSpecialTokens::Sep) if !in_closure => return style_len,
+            Token::Special(CellTextSpecialTokens::LinkStart) => in_closure = true,
+            Token::Special(CellTextSpecialTokens::LinkEnd) => in_closure = false,
+            Token::Special(CellTextSpecialTokens::TemplateStart) => in_closure = true,
+            Token::Special(CellTextSpecialTokens::TemplateEnd) => in_closure = false,
+            _ => {}
+        }
+    }
+    0
+}
+
+// A cursor over the text for parse, which starts with a synthetic `\n`.
+// The line starts from 0, so the real text starts from line 1.
+#[derive(Debug, Clone, Copy)]
+struct Position {
+    offset: usize,
+    line: usize,
+    column: usize,
+}
+
+impl Position {
+    fn start() -> Self {
+        Position {
+            offset: 0,
+            line: 0,
+            column: 1,
+        }
+    }
+
+    fn advance(&mut self, text: &str) {
+        for c in text.chars() {
+            self.offset += c.len_utf8();
+            if c == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+        }
+    }
+
+    fn span_to(&self, end: &Position) -> Span {
+        // offsets are shifted back by the synthetic `\n`
+        Span {
+            start: self.offset.saturating_sub(1),
+            end: end.offset.saturating_sub(1),
+            line: self.line.max(1),
+            column: self.column,
+        }
+    }
+}
+
+// Markup whose content is not parsed as table.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OpaqueKind {
+    NoWiki,
+    Comment,
+    Pre,
+    SyntaxHighlight,
+    Source,
+    Math,
+}
+
+impl OpaqueKind {
+    // the kinds opened by a tag with a name
+    const TAGS: [OpaqueKind; 5] = [
+        OpaqueKind::NoWiki,
+        OpaqueKind::Pre,
+        OpaqueKind::SyntaxHighlight,
+        OpaqueKind::Source,
+        OpaqueKind::Math,
+    ];
+
+    // The kind and the length of the opening tag at the start of the text,
+    // e.g. `<pre class="x">` or `<NOWIKI>`, the length is None if its `>` is
+    // not in the text. `<nowiki />` has nothing inside.
+    fn opening(text: &str) -> Option<(Self, Option<usize>)> {
+        match OpaqueKind::tag_at(text)? {
+            OpaqueKind::Comment => Some((OpaqueKind::Comment, Some(4))),
+            kind => match text.find('>') {
+                Some(end) if text[..end + 1].ends_with("/>") => None,
+                end => Some((kind, end.map(|end| end + 1))),
+            },
+        }
+    }
+
+    // the kind of the opening tag at the start of the text, without
+    // looking for its `>`
+    fn tag_at(text: &str) -> Option<Self> {
+        if text.starts_with("<!--") {
+            return Some(OpaqueKind::Comment);
+        }
+        OpaqueKind::TAGS.into_iter().find(|kind| {
+            let name = kind.tag_name();
+            text.get(1..name.len() + 1)
+                .is_some_and(|tag| text.starts_with('<') && tag.eq_ignore_ascii_case(name))
+                && text[name.len() + 1..].starts_with(|c: char| c == '>' || c.is_whitespace())
+        })
+    }
+
+    fn tag_name(&self) -> &'static str {
+        match self {
+            OpaqueKind::NoWiki => "nowiki",
+            OpaqueKind::Comment => "!--",
+            OpaqueKind::Pre => "pre",
+            OpaqueKind::SyntaxHighlight => "syntaxhighlight",
+            OpaqueKind::Source => "source",
+            OpaqueKind::Math => "math",
+        }
+    }
+
+    fn end_tag(&self) -> &'static str {
+        match self {
+            OpaqueKind::NoWiki => TableSpecialTokens::NoWikiEnd.as_ref(),
+            OpaqueKind::Comment => TableSpecialTokens::CommentEnd.as_ref(),
+            OpaqueKind::Pre => TableSpecialTokens::PreEnd.as_ref(),
+            OpaqueKind::SyntaxHighlight => "</syntaxhighlight>",
+            OpaqueKind::Source => "</source>",
+            OpaqueKind::Math => "</math>",
+        }
+    }
+
+    // The end of the closing tag in the text, e.g. `</PRE >`, or else where
+    // it may start once the text goes on: the text before has none.
+    fn closing_end(&self, text: &str) -> Result<usize, usize> {
+        if *self == OpaqueKind::Comment {
+            return text.find("-->").map(|i| i + 3).ok_or_else(|| {
+                // the `--` of `-->` may be at the end
+                let mut from = text.len().saturating_sub(2);
+                while !text.is_char_boundary(from) {
+                    from -= 1;
+                }
+                from
+            });
+        }
+        let name = self.tag_name();
+        for (i, _) in text.match_indices("</") {
+            match text.get(i + 2..i + 2 + name.len()) {
+                Some(tag) if tag.eq_ignore_ascii_case(name) => {
+                    return text[i..].find('>').map(|end| i + end + 1).ok_or(i)
+                }
+                None if i + 2 + name.len() > text.len() => return Err(i),
+                _ => {}
+            }
+        }
+        Err(match text.ends_with('<') {
+            true => text.len() - 1,
+            false => text.len(),
+        })
+    }
+}
+
+// The longest text kept to read an open tag again as text, in a
+// streaming parse.
+const MAX_OPAQUE_LEN: usize = 1 << 20;
+
+#[derive(Debug)]
+struct OpaqueSpan {
+    kind: OpaqueKind,
+    start: Position,
+    start_span: Span,
+    // the text buffer before the span, to roll back an unclosed span
+    buffer_len: usize,
+    segments_len: usize,
+}
+
+// What to resume once an open table is closed.
+#[derive(Debug)]
+struct OpenTable {
+    id: usize,
+    start: Position,
+    start_span: Span,
+    // whether a cell has been read
+    has_cells: bool,
+    outer_state: State,
+    outer_text_buffer: String,
+    outer_buffer_segments: Vec<(usize, Position)>,
+}
+
+// The reader of a streaming parse.
+struct Source(Box<dyn BufRead + Send>);
+
+impl fmt::Debug for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Source")
+    }
+}
+
 #[pyclass]
 #[derive(Debug)]
 pub struct WikitextTableParser {
     state: State,
-    #[pyo3(get, set)]
-    event_log_queue: Vec<Event>,
-    #[pyo3(get, set)]
-    tokens: Vec<String>,
+    // events not yet taken by the iterator
+    event_log_queue: VecDeque<Event>,
+    // the text for parse, the tokens are read from `position`
+    text: Arc<String>,
+    // the offset of `text` in the whole text, the text before it is dropped
+    // once it is read
+    text_base: usize,
+    // the rest of the text, None once it is all read into `text`
+    source: Option<Source>,
+    // the bytes of a char split by the end of a chunk
+    pending_bytes: Vec<u8>,
+    // whether `finish` has been called by the iterator
+    finished: bool,
     text_buffer: String,
-    table_tokenizer: Tokenizer,
-    cell_tokenizer: Tokenizer,
+    // (offset in the text buffer, position in the text) of each piece of
+    // the text buffer, a new piece starts where a special token is skipped
+    buffer_segments: Vec<(usize, Position)>,
+    // where the token of this step starts, and where the next one starts
+    token_start: Position,
+    position: Position,
+    // the token of this step, without its leading `\n`
+    token_span: Span,
+    table_stack: Vec<OpenTable>,
+    next_table_id: usize,
+    #[pyo3(get)]
+    diagnostics: Vec<Diagnostic>,
+    opaque: Option<OpaqueSpan>,
+    // The offsets from the first unclosed tag of each kind, the ones of
+    // the same kind after it are not closed either, and are read as text.
+    unclosed_opaque: Vec<(OpaqueKind, Range<usize>)>,
+    // the offset after which the text has no `>`, once it is all read
+    tag_end_missing_from: Option<usize>,
+    // the offsets of a text which has no special token, a text token is cut
+    // in it before each opening tag
+    text_run: Option<Range<usize>>,
+    /// Remove comments, and the `<nowiki>` and `<pre>` tags from the text
+    /// of cells, captions and row styles.
+    #[pyo3(get, set)]
+    pub strip_opaque_spans: bool,
+    table_tokenizer: TypedTokenizer<TableSpecialTokens>,
+    cell_tokenizer: TypedTokenizer<CellTextSpecialTokens>,
+    // turn the text of cells into plain text
     clean_cell_text: bool,
+    /// What cleaning the text of cells does with its templates.
+    #[pyo3(get, set)]
+    pub template_policy: TemplatePolicy,
+    // expands the templates of the text of cells, before it is cleaned
+    template_expander: Option<TemplateExpander>,
 }
 
 impl Iterator for WikitextTableParser {
     type Item = Event;
 
+    // step only until the next event is available
     fn next(&mut self) -> Option<Event> {
-        while self.tokens.len() > 0 {
-            self.step();
+        loop {
+            if let Some(event) = self.event_log_queue.pop_front() {
+                return Some(event);
+            }
+            if !self.at_end() {
+                self.step();
+            } else if !self.finished {
+                self.finished = true;
+                self.finish();
+            } else {
+                return None;
+            }
+        }
+    }
+}
+
+impl WikitextTableParser {
+    fn buffer_position(&self, index: usize) -> Position {
+        let (segment_start, mut position) = self
+            .buffer_segments
+            .iter()
+            .rev()
+            .find(|(segment_start, _)| *segment_start <= index)
+            .copied()
+            .unwrap_or((0, self.token_start));
+        if let Some(text) = self.text_buffer.get(segment_start..index) {
+            position.advance(text);
+        }
+        position
+    }
+
+    fn buffer_span(&self, start: usize, end: usize) -> Span {
+        self.buffer_position(start)
+            .span_to(&self.buffer_position(end))
+    }
+
+    // an empty span where the token of this step starts
+    fn empty_token_span(&self) -> Span {
+        Span {
+            end: self.token_span.start,
+            ..self.token_span
+        }
+    }
+
+    // byte range of the trimmed text buffer
+    fn trimmed_buffer_range(&self) -> (usize, usize) {
+        let end = self.text_buffer.trim_end().len();
+        let start = end - self.text_buffer[..end].trim_start().len();
+        (start, end)
+    }
+
+    fn cell_style_len(&self, cell_text: &str) -> usize {
+        cell_style_len(&self.cell_tokenizer, cell_text)
+    }
+
+    fn raw_buffer_data(&self) -> (String, Span) {
+        // table attributes are never split by `|`
+        let (start, end) = self.trimmed_buffer_range();
+        (
+            self.text_buffer[start..end].to_string(),
+            self.buffer_span(start, end),
+        )
+    }
+
+    fn style_buffer_data(&self) -> (String, Span) {
+        let (start, end) = self.trimmed_buffer_range();
+        let style_end = start + self.cell_style_len(&self.text_buffer[start..end]);
+        (
+            self.text_buffer[start..style_end].to_string(),
+            self.buffer_span(start, style_end),
+        )
+    }
+
+    fn text_buffer_data(&self) -> (String, Span) {
+        let (start, end) = self.trimmed_buffer_range();
+        let text_start = start + self.cell_style_len(&self.text_buffer[start..end]);
+        let text = &self.text_buffer[text_start..end];
+        let text = match self.strip_opaque_spans {
+            true => strip_opaque_spans(text),
+            false => text.to_string(),
+        };
+        (text, self.buffer_span(text_start, end))
+    }
+
+    fn push_to_text_buffer(&mut self, s: &str, start: Position) {
+        // start a new piece unless it follows the last one in the text
+        let follows_last_segment =
+            self.buffer_segments
+                .last()
+                .is_some_and(|(segment_start, position)| {
+                    position.offset + self.text_buffer.len() - segment_start == start.offset
+                });
+        if !follows_last_segment {
+            self.buffer_segments.push((self.text_buffer.len(), start));
+        }
+        self.text_buffer += s;
+    }
+
+    // Read the token as a part of `<nowiki>`, `<pre>`, `<math>` and the like
+    // or a comment, returns false if the token is not in any of them.
+    fn step_opaque(&mut self, token: Token<TableSpecialTokens>) -> bool {
+        if self.opaque.is_none() {
+            let kind = match OpaqueKind::opening(token.as_str()) {
+                Some((kind, Some(len))) if len == token.as_str().len() => kind,
+                _ => return false,
+            };
+            // an unclosed tag is read as text
+            let offset = self.token_start.offset;
+            let unclosed = self
+                .unclosed_opaque
+                .iter()
+                .any(|(unclosed, range)| *unclosed == kind && range.contains(&offset));
+            if unclosed {
+                if !matches!(self.state, State::Idle) {
+                    self.push_to_text_buffer(token.as_str(), self.token_start);
+                }
+                return true;
+            }
+            self.opaque = Some(OpaqueSpan {
+                kind,
+                start: self.token_start,
+                start_span: self.token_span,
+                buffer_len: self.text_buffer.len(),
+                segments_len: self.buffer_segments.len(),
+            });
+        } else if self
+            .opaque
+            .as_ref()
+            .is_some_and(|opaque| opaque.kind.closing_end(token.as_str()).is_ok())
+        {
+            self.opaque = None;
+        }
+
+        // kept as it is in the text buffer, including the tags
+        if !matches!(self.state, State::Idle) {
+            self.push_to_text_buffer(token.as_str(), self.token_start);
+        }
+        true
+    }
+
+    // The next token of the text: the content of an open `<nowiki>`, `<pre>`,
+    // `<math>` and the like or comment up to its closing tag, an opening tag, or a token of the
+    // table tokenizer. A text token ends before an opening tag.
+    fn next_token(&mut self, rest: &str) -> Option<(Option<TableSpecialTokens>, usize)> {
+        if let Some(opaque) = &self.opaque {
+            return match opaque.kind.closing_end(rest) {
+                Ok(end) => Some((None, end)),
+                // the text without the closing tag is read before the next
+                // chunk, so it is not searched again
+                Err(from) if self.source.is_some() => (from > 0).then_some((None, from)),
+                Err(_) => (!rest.is_empty()).then_some((None, rest.len())),
+            };
         }
+        match self.opening_at(rest, 0) {
+            Some((_, Some(len))) => return Some((None, len)),
+            // its `>` may be in the next chunk
+            Some((_, None)) if self.source.is_some() => return Some((None, rest.len())),
+            _ => {}
+        }
+        let offset = self.position.offset;
+        let token = match &self.text_run {
+            Some(run) if run.contains(&offset) => Token::Text(&rest[..run.end - offset]),
+            _ => self.table_tokenizer.tokens(rest).next()?,
+        };
+        match token {
+            Token::Special(special) => Some((Some(special), token.as_str().len())),
+            Token::Text(text) => {
+                let len = text
+                    .match_indices('<')
+                    .map(|(i, _)| i)
+                    .find(|&i| {
+                        i > 0
+                            && self
+                                .opening_at(rest, i)
+                                .is_some_and(|(_, len)| len.is_some() || self.source.is_some())
+                    })
+                    .unwrap_or(text.len());
+                // The rest of the text is not tokenized again by the next
+                // steps. A special token at the end of a chunk may be longer
+                // in the next one, so it is known once it is clear of the end.
+                let longest = self
+                    .table_tokenizer
+                    .tokenizer()
+                    .special_tokens()
+                    .iter()
+                    .map(String::len)
+                    .max()
+                    .unwrap_or(0);
+                if len < text.len() && (self.source.is_none() || text.len() + longest < rest.len())
+                {
+                    self.text_run = Some(offset..offset + text.len());
+                }
+                if len == rest.len() && self.source.is_some() {
+                    // only the tail which may start a token in the next chunk
+                    // waits for it, so the text is not read again and again
+                    let longest = OpaqueKind::TAGS
+                        .iter()
+                        .map(|kind| kind.tag_name().len() + 2)
+                        .fold(longest, usize::max);
+                    let end = self.chunk_text_end(rest, longest);
+                    return Some((None, if end > 0 { end } else { rest.len() }));
+                }
+                Some((None, len))
+            }
+        }
+    }
+
+    // Where a text at the end of a chunk is cut: a special token or an
+    // opening tag which goes on in the next chunk starts in its last bytes,
+    // 0 if the text is too short to be cut.
+    fn chunk_text_end(&self, rest: &str, longest: usize) -> usize {
+        let mut from = rest.len().saturating_sub(longest);
+        while !rest.is_char_boundary(from) {
+            from -= 1;
+        }
+        let special_tokens = self.table_tokenizer.tokenizer().special_tokens();
+        rest[from..]
+            .char_indices()
+            .find(|&(_, c)| c == '<' || special_tokens.iter().any(|token| token.starts_with(c)))
+            .map_or(from, |(i, _)| from + i)
+    }
+
+    // `OpaqueKind::opening` at `i` of the rest of the text. Once the text is
+    // all read, a `>` is not looked for again after where none was found.
+    fn opening_at(&mut self, rest: &str, i: usize) -> Option<(OpaqueKind, Option<usize>)> {
+        let offset = self.position.offset + i;
+        let kind = OpaqueKind::tag_at(&rest[i..])?;
+        if kind != OpaqueKind::Comment
+            && self.source.is_none()
+            && self.tag_end_missing_from.is_some_and(|from| offset >= from)
+        {
+            return Some((kind, None));
+        }
+        let opening = OpaqueKind::opening(&rest[i..]);
+        if self.source.is_none() && opening.is_some_and(|(_, len)| len.is_none()) {
+            self.tag_end_missing_from = Some(offset);
+        }
+        opening
+    }
+
+    // At the end of the text, an unclosed comment hides everything after it,
+    // an unclosed tag such as `<nowiki>` or `<pre>` is read again as text.
+    fn finish_opaque(&mut self) {
+        while let Some(opaque) = self.opaque.take() {
+            if opaque.kind == OpaqueKind::Comment {
+                self.report(
+                    Severity::Warning,
+                    DiagnosticCode::UnclosedMarkup,
+                    &format!("`{}` is not found", opaque.kind.end_tag()),
+                    opaque.start_span,
+                );
+                break;
+            }
+            self.roll_back_opaque(opaque, None);
+            while !self.at_end() {
+                self.step();
+            }
+        }
+    }
+
+    // Read an unclosed tag again as text, and the tags of its kind up to
+    // `until`, or up to the end of the text if None.
+    fn roll_back_opaque(&mut self, opaque: OpaqueSpan, until: Option<usize>) {
+        let message = match until {
+            Some(_) => format!(
+                "`{}` is not found in {} bytes",
+                opaque.kind.end_tag(),
+                MAX_OPAQUE_LEN
+            ),
+            None => format!("`{}` is not found", opaque.kind.end_tag()),
+        };
+        self.report(
+            Severity::Warning,
+            DiagnosticCode::UnclosedMarkup,
+            &message,
+            opaque.start_span,
+        );
+        self.text_buffer.truncate(opaque.buffer_len);
+        self.buffer_segments.truncate(opaque.segments_len);
+        self.position = opaque.start;
+        self.unclosed_opaque
+            .push((opaque.kind, opaque.start.offset..until.unwrap_or(usize::MAX)));
+    }
+
+    fn emit_table_style(&mut self) {
+        let (text, span) = self.raw_buffer_data();
+        self.transition(Event::TableStyle { text, span });
+        self.clear_text_buffer();
+    }
+
+    fn emit_row_style(&mut self) {
+        let (text, span) = self.text_buffer_data();
+        self.transition(Event::RowStyle { text, span });
+        self.clear_text_buffer();
+    }
+
+    fn emit_table_caption(&mut self) {
+        let (style, style_span) = self.style_buffer_data();
+        self.transition(Event::TableCaptionStyle {
+            text: style,
+            span: style_span,
+        });
+        let (text, span) = self.text_buffer_data();
+        self.transition(Event::TableCaption { text, span });
+        self.clear_text_buffer();
+    }
 
-        if self.event_log_queue.len() > 0 {
-            let event = self.event_log_queue.remove(0);
-            return Some(event);
+    fn end_cell(&mut self) {
+        let (style, style_span) = self.style_buffer_data();
+        let (mut text, text_span) = self.text_buffer_data();
+        if let Some(expander) = &self.template_expander {
+            text = expander.expand_text(&text);
         }
-        return None;
+        if self.clean_cell_text {
+            text = clean_col_text_with_policy(&text, self.template_policy);
+        }
+        self.transition(Event::ColStyle {
+            text: style,
+            span: style_span,
+        });
+        self.transition(Event::ColEnd {
+            text,
+            span: text_span,
+        });
+        self.clear_text_buffer();
+    }
+
+    fn start_cell(&mut self, cell_type: CellType) {
+        if let Some(open_table) = self.table_stack.last_mut() {
+            open_table.has_cells = true;
+        }
+        self.transition(Event::ColStart {
+            cell_type,
+            span: self.token_span,
+        });
+    }
+
+    fn start_row_with_cell(&mut self, cell_type: CellType) {
+        // even we do not read the `|-` (row start token)
+        // we still send the read row event,
+        // after that send a col start event (due to we match `!` or `|`)
+        self.transition(Event::RowStart {
+            span: self.token_span,
+        });
+        self.transition(Event::RowStyle {
+            text: String::from(""),
+            span: self.empty_token_span(),
+        });
+        self.start_cell(cell_type);
+    }
+
+    fn open_table(&mut self) {
+        // keep the outer cell aside, it is resumed when this table is closed
+        let id = self.next_table_id;
+        self.next_table_id += 1;
+        self.table_stack.push(OpenTable {
+            id,
+            start: self.token_start,
+            start_span: self.token_span,
+            has_cells: false,
+            outer_state: self.state,
+            outer_text_buffer: std::mem::take(&mut self.text_buffer),
+            outer_buffer_segments: std::mem::take(&mut self.buffer_segments),
+        });
+        self.transition(Event::TableStart {
+            id,
+            depth: self.table_stack.len() - 1,
+            span: self.token_span,
+        });
+    }
+
+    fn report(&mut self, severity: Severity, code: DiagnosticCode, message: &str, span: Span) {
+        self.diagnostics.push(Diagnostic {
+            severity,
+            code,
+            message: message.to_string(),
+            span,
+        });
+    }
+
+    // `|+` in a row, read it as the caption of the table
+    fn start_late_caption(&mut self) {
+        if self.table_stack.last().is_some_and(|t| t.has_cells) {
+            self.report(
+                Severity::Warning,
+                DiagnosticCode::CaptionAfterRow,
+                "the table caption `|+` should be before the first row",
+                self.token_span,
+            );
+        }
+        self.transition(Event::RowEnd {
+            span: self.empty_token_span(),
+        });
+        self.transition(Event::TableCaptionStart {
+            span: self.token_span,
+        });
+    }
+
+    /// Parse the text read from the reader. The text is read as the events
+    /// are taken, only the text of the token being read is kept, and up to
+    /// 1 MiB of an open `<nowiki>`, `<pre>`, `<math>` and the like: one not
+    /// closed by then is read as text, as it is at the end of the text.
+    pub fn from_reader<R: BufRead + Send + 'static>(
+        table_tokenizer: Tokenizer,
+        cell_tokenizer: Tokenizer,
+        reader: R,
+        clean_cell_text: bool,
+    ) -> Self {
+        let mut parser =
+            WikitextTableParser::new(table_tokenizer, cell_tokenizer, "", clean_cell_text);
+        parser.source = Some(Source(Box::new(reader)));
+        parser
+    }
+
+    fn at_end(&self) -> bool {
+        self.source.is_none() && self.position.offset - self.text_base >= self.text.len()
+    }
+
+    // Read the next chunk of the source, and drop the text already read.
+    fn read_source(&mut self) {
+        let source = match self.source.as_mut() {
+            Some(source) => source,
+            None => return,
+        };
+        let mut bytes = std::mem::take(&mut self.pending_bytes);
+        let mut eof = false;
+        match source.0.fill_buf() {
+            Ok([]) => eof = true,
+            Ok(buf) => {
+                bytes.extend_from_slice(buf);
+                let len = buf.len();
+                source.0.consume(len);
+            }
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => {
+                eof = true;
+                self.report(
+                    Severity::Error,
+                    DiagnosticCode::ReadError,
+                    &format!("failed to read the text: {}", err),
+                    self.position.span_to(&self.position),
+                );
+            }
+        }
+        if eof {
+            self.source = None;
+        }
+
+        // invalid UTF-8 is replaced by U+FFFD, a char split by the end of
+        // the chunk waits for the next one
+        let mut chunk = String::new();
+        let mut rest = &bytes[..];
+        while !rest.is_empty() {
+            match std::str::from_utf8(rest) {
+                Ok(text) => {
+                    chunk += text;
+                    rest = &[];
+                }
+                Err(err) => {
+                    let (valid, after) = rest.split_at(err.valid_up_to());
+                    chunk += std::str::from_utf8(valid).unwrap();
+                    match err.error_len() {
+                        Some(len) => {
+                            chunk.push(char::REPLACEMENT_CHARACTER);
+                            rest = &after[len..];
+                        }
+                        None if eof => {
+                            chunk.push(char::REPLACEMENT_CHARACTER);
+                            rest = &[];
+                        }
+                        None => {
+                            self.pending_bytes = after.to_vec();
+                            rest = &[];
+                        }
+                    }
+                }
+            }
+        }
+
+        // keep an open tag, it is read again if it is not closed, the rest
+        // of the text is hidden by an open comment anyway
+        let keep_from = match &self.opaque {
+            Some(opaque) if opaque.kind != OpaqueKind::Comment => {
+                opaque.start.offset.min(self.position.offset)
+            }
+            _ => self.position.offset,
+        } - self.text_base;
+        let text = Arc::make_mut(&mut self.text);
+        text.drain(..keep_from);
+        text.push_str(&chunk);
+        self.text_base += keep_from;
+    }
+
+    /// Expand the templates of the text of cells by the handler, e.g.
+    /// `BuiltinTemplates`. The templates it does not expand are left to
+    /// the `template_policy`.
+    pub fn set_template_handler(&mut self, handler: impl TemplateHandler + 'static) {
+        let inline_parser = InlineParser::new(self.cell_tokenizer.tokenizer().clone());
+        self.template_expander = Some(TemplateExpander::with_parser(handler, inline_parser));
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Parse all the tokens, fails if there is any diagnostic.
+    pub fn parse_strict(mut self) -> Result<Vec<Event>, ParseError> {
+        let events: Vec<Event> = self.by_ref().collect();
+        if self.diagnostics.is_empty() {
+            Ok(events)
+        } else {
+            Err(ParseError {
+                diagnostics: self.diagnostics,
+            })
+        }
+    }
+
+    fn close_table(&mut self) {
+        let open_table = match self.table_stack.last() {
+            Some(open_table) => open_table,
+            None => return,
+        };
+        self.transition(Event::TableEnd {
+            id: open_table.id,
+            depth: self.table_stack.len() - 1,
+            span: self.token_span,
+        });
     }
 }
 
@@ -79,268 +918,356 @@ impl WikitextTableParser {
     ) -> Self {
         // add `\n` at start to match `\n{|`, even it is at the first of context.
         let text_for_parse: String = String::from("\n") + wikitext_table;
-        let parser = WikitextTableParser {
+        WikitextTableParser {
             state: State::Idle,
-            tokens: table_tokenizer.tokenize(&text_for_parse),
-            event_log_queue: Vec::new(),
+            event_log_queue: VecDeque::new(),
+            text: Arc::new(text_for_parse),
+            text_base: 0,
+            source: None,
+            pending_bytes: Vec::new(),
+            finished: false,
             text_buffer: String::from(""),
-            table_tokenizer: table_tokenizer,
-            cell_tokenizer: cell_tokenizer,
-            clean_cell_text: clean_cell_text,
-        };
+            buffer_segments: Vec::new(),
+            token_start: Position::start(),
+            position: Position::start(),
+            token_span: Span::default(),
+            table_stack: Vec::new(),
+            next_table_id: 0,
+            diagnostics: Vec::new(),
+            opaque: None,
+            unclosed_opaque: Vec::new(),
+            tag_end_missing_from: None,
+            text_run: None,
+            strip_opaque_spans: false,
+            table_tokenizer: TypedTokenizer::new(table_tokenizer),
+            cell_tokenizer: TypedTokenizer::new(cell_tokenizer),
+            clean_cell_text,
+            template_policy: TemplatePolicy::default(),
+            template_expander: None,
+        }
+    }
 
-        // println!("{:?}",parser.tokens);
-        return parser;
+    /// Expand the templates of the text of cells by `BuiltinTemplates`.
+    fn use_builtin_templates(&mut self) {
+        self.set_template_handler(BuiltinTemplates);
     }
 
-    fn append_to_text_buffer(&mut self, s: &str) {
-        let token = TableSpecialTokens::from_str(s);
-        match token {
-            Ok(_) => {
-                // do nothing if is a special token
-            }
-            Err(_) => {
-                self.text_buffer += s;
-            }
-        }
+    #[getter]
+    fn get_event_log_queue(&self) -> Vec<Event> {
+        self.event_log_queue.iter().cloned().collect()
     }
 
-    fn clear_text_buffer(&mut self) {
-        self.text_buffer = String::from("")
-    }
-
-    fn split_cell_style_and_text(&self, cell_text: String) -> Vec<String> {
-        let cell_tokens = self.cell_tokenizer.tokenize(&cell_text);
-        let mut style = String::new();
-        let mut cell_text = String::new();
-        let mut temp = String::new();
-        let mut already_match_style_end = false;
-        let mut in_closure = false;
-        for token in cell_tokens {
-            match CellTextSpecialTokens::from_str(token.as_str()) {
-                Ok(cell_text_sp_token) => match cell_text_sp_token {
-                    CellTextSpecialTokens::Sep => {
-                        if !in_closure {
-                            already_match_style_end = true;
-                        }
-                    }
-                    CellTextSpecialTokens::LinkStart => in_closure = true,
-                    CellTextSpecialTokens::LinkEnd => in_closure = false,
-                    CellTextSpecialTokens::TemplateStart => in_closure = true,
-                    CellTextSpecialTokens::TemplateEnd => in_closure = false,
+    #[setter]
+    fn set_event_log_queue(&mut self, events: Vec<Event>) {
+        self.event_log_queue = events.into();
+    }
 
-                    _ => {}
-                },
-                Err(_) => {}
-            }
-            temp += &token;
-            if already_match_style_end && style.len() == 0 {
-                style = temp.clone();
-                temp = String::new();
-            }
-        }
+    // the tokens not yet stepped
+    #[getter]
+    fn get_tokens(&self) -> Vec<String> {
+        self.table_tokenizer
+            .tokenizer()
+            .tokenize(&self.text[self.position.offset - self.text_base..])
+    }
 
-        cell_text = temp;
+    #[setter]
+    fn set_tokens(&mut self, tokens: Vec<String>) {
+        let text =
+            self.text[..self.position.offset - self.text_base].to_string() + &tokens.concat();
+        self.text = Arc::new(text);
+    }
 
-        return vec![style, cell_text];
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
     }
 
-    fn get_text_buffer_data(&self) -> String {
-        let cell_raw_text = self.text_buffer.clone().trim().to_string();
-        let split_texts = self.split_cell_style_and_text(cell_raw_text);
-        let style: String = split_texts[0].clone();
-        let cell_text = split_texts[1].clone();
-        // println!("{:?}",style);
-        return cell_text;
+    fn __next__(mut slf: PyRefMut<'_, Self>) -> Option<Event> {
+        slf.next()
     }
 
-    fn get_style_text_buffer_data(&self) -> String {
-        let cell_raw_text = self.text_buffer.clone().trim().to_string();
-        let split_texts = self.split_cell_style_and_text(cell_raw_text);
-        let style = split_texts[0].clone();
-        let cell_text = split_texts[1].clone();
-        // println!("{:?}",style);
-        return style;
+    fn clear_text_buffer(&mut self) {
+        self.text_buffer = String::from("");
+        self.buffer_segments.clear();
     }
 
-    fn step(&mut self) {
-        let token = self.tokens.remove(0);
-        // println!("{:?}", token);
-        match self.state {
-            State::Idle => {
-                if &token == TableSpecialTokens::TableStart.as_ref() {
-                    self.transition(Event::TableStart {})
-                }
-            }
-            State::ReadTable => {
-                self.append_to_text_buffer(&token);
-                if &token == TableSpecialTokens::TableCaption.as_ref() {
-                    self.transition(Event::TableStyle {
-                        text: self.get_text_buffer_data(),
-                    });
-                    self.clear_text_buffer();
-                    self.transition(Event::TableCaptionStart {});
-                } else if &token == TableSpecialTokens::TableRow.as_ref() {
-                    self.transition(Event::TableStyle {
-                        text: self.get_style_text_buffer_data(),
-                    });
+    /// Close the tables left open at the end of the text, called once all
+    /// the tokens are stepped.
+    fn finish(&mut self) {
+        self.finish_opaque();
+        // everything left ends where the text ends
+        self.token_start = self.position;
+        self.token_span = self.position.span_to(&self.position);
+        while let Some(open_table) = self.table_stack.last() {
+            self.report(
+                Severity::Error,
+                DiagnosticCode::UnterminatedTable,
+                "the table is not closed by `|}`",
+                open_table.start_span,
+            );
+            match self.state {
+                State::ReadTable => self.emit_table_style(),
+                State::ReadTableCaption => self.emit_table_caption(),
+                State::ReadRow => {
                     self.clear_text_buffer();
-                    self.transition(Event::RowStart {});
-                } else if &token == TableSpecialTokens::TableHeaderCell.as_ref() {
-                    self.transition(Event::TableStyle {
-                        text: self.get_text_buffer_data(),
+                    self.transition(Event::RowEnd {
+                        span: self.token_span,
                     });
-                    self.clear_text_buffer();
-                    self.transition(Event::RowStart {});
-                }
-                // end of table
-                else if &token == TableSpecialTokens::TableEnd.as_ref() {
-                    self.transition(Event::TableEnd {});
                 }
-            }
-
-            State::ReadTableCaption => {
-                self.append_to_text_buffer(&token);
-                if &token == TableSpecialTokens::TableRow.as_ref() {
-                    self.transition(Event::TableCaption {
-                        text: self.get_text_buffer_data(),
-                    });
-                    self.clear_text_buffer();
-                    self.transition(Event::RowStart {});
-                }
-                // match ! after the caption, this type will not have a row style
-                // and should turn in to read col state
-                else if &token == TableSpecialTokens::TableHeaderCell.as_ref() {
-                    // catch table caption and trans the state to "State::ReadTable"
-                    self.transition(Event::TableCaption {
-                        text: self.get_text_buffer_data(),
-                    });
-                    self.clear_text_buffer();
-                    // even we do not read the `|-` (row start token)
-                    // we still send the read row event,
-                    // after that send a col start event (due to we match `!`)
-                    self.transition(Event::RowStart {});
-                    self.transition(Event::RowStyle {
-                        text: String::from(""),
-                    });
-                    self.transition(Event::ColStart {
-                        cell_type: CellType::HeaderCell,
+                State::ReadCol => {
+                    self.end_cell();
+                    self.transition(Event::RowEnd {
+                        span: self.token_span,
                     });
                 }
+                State::Idle => {}
             }
+            self.close_table();
+        }
+    }
 
-            State::ReadRow => {
-                self.append_to_text_buffer(&token);
-                if &token == TableSpecialTokens::TableDataCell.as_ref()
-                    || &token == TableSpecialTokens::TableDataCell2.as_ref()
+    fn step(&mut self) {
+        let (special, len) = loop {
+            // an open tag which is not closed in `MAX_OPAQUE_LEN` bytes is
+            // read again as text, so the text kept for it is bounded
+            let text_end = self.text_base + self.text.len();
+            if let Some(opaque) = &self.opaque {
+                if opaque.kind != OpaqueKind::Comment
+                    && self.source.is_some()
+                    && text_end - opaque.start.offset > MAX_OPAQUE_LEN
                 {
-                    self.transition(Event::RowStyle {
-                        text: self.get_text_buffer_data(),
-                    });
-                    self.clear_text_buffer();
-                    self.transition(Event::ColStart {
-                        cell_type: CellType::DataCell,
-                    });
-                } else if &token == TableSpecialTokens::TableHeaderCell.as_ref()
-                    || &token == TableSpecialTokens::TableHeaderCell2.as_ref()
+                    let opaque = self.opaque.take().unwrap();
+                    self.roll_back_opaque(opaque, Some(text_end));
+                }
+            }
+            let (next, rest_len) = {
+                // the rest borrows the text, which is kept by the clone
+                let text = Arc::clone(&self.text);
+                let rest = &text[self.position.offset - self.text_base..];
+                (self.next_token(rest), rest.len())
+            };
+            match next {
+                // a token at the end of the chunk may go on in the next one,
+                // but the text of an open tag or comment is already cut
+                Some((_, len))
+                    if len == rest_len && self.source.is_some() && self.opaque.is_none() =>
                 {
-                    self.transition(Event::RowStyle {
-                        text: self.get_text_buffer_data(),
-                    });
-                    self.clear_text_buffer();
-                    self.transition(Event::ColStart {
-                        cell_type: CellType::HeaderCell,
-                    });
-                } else if &token == TableSpecialTokens::TableEnd.as_ref() {
-                    self.transition(Event::RowEnd {});
-                    self.transition(Event::TableEnd {});
-                    self.clear_text_buffer();
+                    self.read_source()
                 }
+                Some(next) => break next,
+                None if self.source.is_some() => self.read_source(),
+                None => return,
             }
+        };
+        // the token borrows the text, which is kept by the clone
+        let text = Arc::clone(&self.text);
+        let start = self.position.offset - self.text_base;
+        let token = match special {
+            Some(special) => Token::Special(special),
+            None => Token::Text(&text[start..start + len]),
+        };
+        self.token_start = self.position;
+        self.position.advance(token.as_str());
+        let mut token_span_start = self.token_start;
+        if token.as_str().len() > 1 && token.as_str().starts_with('\n') {
+            token_span_start.advance("\n");
+        }
+        self.token_span = token_span_start.span_to(&self.position);
 
-            State::ReadCol => {
-                self.append_to_text_buffer(&token);
+        if self.step_opaque(token) {
+            return;
+        }
 
-                // match \n| or \n||
-                if &token == TableSpecialTokens::TableDataCell.as_ref()
-                    || &token == TableSpecialTokens::TableDataCell2.as_ref()
-                {
-                    self.transition(Event::ColStyle {
-                        text: self.get_style_text_buffer_data(),
-                    });
-                    self.transition(Event::ColEnd {
-                        text: self.get_text_buffer_data(),
-                    });
-                    self.clear_text_buffer();
-                    self.transition(Event::ColStart {
-                        cell_type: CellType::DataCell,
-                    });
-                }
-                // match \n! or \n!!
-                else if &token == TableSpecialTokens::TableHeaderCell.as_ref()
-                    || &token == TableSpecialTokens::TableHeaderCell2.as_ref()
-                {
-                    self.transition(Event::ColStyle {
-                        text: self.get_style_text_buffer_data(),
-                    });
-                    self.transition(Event::ColEnd {
-                        text: self.get_text_buffer_data(),
-                    });
-                    self.clear_text_buffer();
-                    self.transition(Event::ColStart {
-                        cell_type: CellType::HeaderCell,
-                    });
-                } else if &token == TableSpecialTokens::TableRow.as_ref() {
-                    self.transition(Event::ColStyle {
-                        text: self.get_style_text_buffer_data(),
-                    });
-                    self.transition(Event::ColEnd {
-                        text: self.get_text_buffer_data(),
-                    });
-                    self.clear_text_buffer();
-                    self.transition(Event::RowEnd {});
-                    self.transition(Event::RowStart {});
-                } else if &token == TableSpecialTokens::TableEnd.as_ref() {
-                    self.transition(Event::ColStyle {
-                        text: self.get_style_text_buffer_data(),
-                    });
-                    self.transition(Event::ColEnd {
-                        text: self.get_text_buffer_data(),
-                    });
-                    self.clear_text_buffer();
-                    self.transition(Event::RowEnd {});
-                    self.transition(Event::TableEnd {});
+        // special tokens are not kept in the text buffer, but a closing tag
+        // out of any span is text, e.g. the `-->` of `a --> b`
+        use TableSpecialTokens::*;
+        let special = match token {
+            Token::Special(special)
+                if !matches!(
+                    special,
+                    NoWikiStart | NoWikiEnd | CommentStart | CommentEnd | PreStart | PreEnd
+                ) =>
+            {
+                special
+            }
+            _ => {
+                if !matches!(self.state, State::Idle) {
+                    self.push_to_text_buffer(token.as_str(), self.token_start);
                 }
+                return;
+            }
+        };
+
+        match (self.state, special) {
+            (State::Idle, TableStart) => self.open_table(),
+            (State::Idle, TableRow) => self.report(
+                Severity::Warning,
+                DiagnosticCode::RowOutsideTable,
+                "the table row `|-` is not in a table",
+                self.token_span,
+            ),
+
+            (State::ReadTable, TableCaption) => {
+                self.emit_table_style();
+                self.transition(Event::TableCaptionStart {
+                    span: self.token_span,
+                });
+            }
+            (State::ReadTable, TableRow) => {
+                self.emit_table_style();
+                self.transition(Event::RowStart {
+                    span: self.token_span,
+                });
+            }
+            // the first row may omit `|-` and start with a cell
+            (State::ReadTable, TableHeaderCell) => {
+                self.emit_table_style();
+                self.start_row_with_cell(CellType::HeaderCell);
+            }
+            (State::ReadTable, TableDataCell) => {
+                self.emit_table_style();
+                self.start_row_with_cell(CellType::DataCell);
+            }
+            // end of a table without rows, its attributes are kept as well
+            (State::ReadTable, TableEnd) => {
+                self.emit_table_style();
+                self.close_table();
+            }
+
+            // a table inside of the caption
+            (State::ReadTableCaption, TableStart) => self.open_table(),
+            (State::ReadTableCaption, TableRow) => {
+                self.emit_table_caption();
+                self.transition(Event::RowStart {
+                    span: self.token_span,
+                });
+            }
+            // match ! after the caption, this type will not have a row style
+            // and should turn in to read col state
+            (State::ReadTableCaption, TableHeaderCell) => {
+                // catch table caption and trans the state to "State::ReadTable"
+                self.emit_table_caption();
+                self.start_row_with_cell(CellType::HeaderCell);
+            }
+            (State::ReadTableCaption, TableDataCell) => {
+                self.emit_table_caption();
+                self.start_row_with_cell(CellType::DataCell);
+            }
+            (State::ReadTableCaption, TableEnd) => {
+                self.emit_table_caption();
+                self.close_table();
+            }
+
+            // a table between `|-` and the first cell of the row
+            (State::ReadRow, TableStart) => self.open_table(),
+            (State::ReadRow, TableDataCell | TableDataCell2) => {
+                self.emit_row_style();
+                self.start_cell(CellType::DataCell);
+            }
+            (State::ReadRow, TableHeaderCell | TableHeaderCell2) => {
+                self.emit_row_style();
+                self.start_cell(CellType::HeaderCell);
             }
+            // a row with no cells of its own, e.g. covered by the rowspans above
+            (State::ReadRow, TableRow) => {
+                self.emit_row_style();
+                self.transition(Event::RowEnd {
+                    span: self.empty_token_span(),
+                });
+                self.transition(Event::RowStart {
+                    span: self.token_span,
+                });
+            }
+            (State::ReadRow, TableCaption) => {
+                self.clear_text_buffer();
+                self.start_late_caption();
+            }
+            (State::ReadRow, TableEnd) => {
+                // before the outer buffer is restored by `TableEnd`
+                self.clear_text_buffer();
+                self.transition(Event::RowEnd {
+                    span: self.empty_token_span(),
+                });
+                self.close_table();
+            }
+
+            // a table inside of the cell
+            (State::ReadCol, TableStart) => self.open_table(),
+            // match \n| or \n||
+            (State::ReadCol, TableDataCell | TableDataCell2) => {
+                self.end_cell();
+                self.start_cell(CellType::DataCell);
+            }
+            // match \n! or \n!!
+            (State::ReadCol, TableHeaderCell | TableHeaderCell2) => {
+                self.end_cell();
+                self.start_cell(CellType::HeaderCell);
+            }
+            (State::ReadCol, TableRow) => {
+                self.end_cell();
+                self.transition(Event::RowEnd {
+                    span: self.empty_token_span(),
+                });
+                self.transition(Event::RowStart {
+                    span: self.token_span,
+                });
+            }
+            (State::ReadCol, TableCaption) => {
+                self.end_cell();
+                self.start_late_caption();
+            }
+            (State::ReadCol, TableEnd) => {
+                self.end_cell();
+                self.transition(Event::RowEnd {
+                    span: self.empty_token_span(),
+                });
+                self.close_table();
+            }
+
+            _ => {}
         }
     }
 
     fn transition(&mut self, event: Event) {
         // println!(" -> {:?},{:?}", self.state, event);
-        self.event_log_queue.push(event.clone());
+        self.event_log_queue.push_back(event.clone());
         match (self.state, event) {
-            // State::Idle
-            (State::Idle, Event::TableStart {}) => self.state = State::ReadTable,
+            // a table could be started from State::Idle or inside of a cell
+            (_, Event::TableStart { .. }) => self.state = State::ReadTable,
 
             // State::ReadTableCaption
-            (State::ReadTableCaption, Event::TableCaption { text }) => {
-                self.state = State::ReadTable
-            }
+            (State::ReadTableCaption, Event::TableCaption { .. }) => self.state = State::ReadTable,
+
+            // the caption is expected in State::ReadTable, but is also
+            // accepted after a row
+            (_, Event::TableCaptionStart { .. }) => self.state = State::ReadTableCaption,
 
             // State::ReadTable
-            (State::ReadTable, Event::TableCaptionStart {}) => self.state = State::ReadTableCaption,
-            (State::ReadTable, Event::TableEnd {}) => {
-                self.state = State::Idle;
-            }
-            (State::ReadTable, Event::RowStart {}) => self.state = State::ReadRow,
+            (State::ReadTable, Event::RowStart { .. }) => self.state = State::ReadRow,
 
             // State::ReadRow
-            (State::ReadRow, Event::ColStart { cell_type }) => self.state = State::ReadCol,
+            (State::ReadRow, Event::ColStart { .. }) => self.state = State::ReadCol,
 
             // State::ReadCol
-            (State::ReadCol, Event::ColStyle { text }) => {}
-            (State::ReadCol, Event::ColEnd { text }) => self.state = State::ReadCol,
-            (State::ReadCol, Event::RowStart {}) => self.state = State::ReadRow,
+            (State::ReadCol, Event::ColStyle { .. }) => {}
+            (State::ReadCol, Event::ColEnd { .. }) => self.state = State::ReadCol,
+            (State::ReadCol, Event::RowStart { .. }) => self.state = State::ReadRow,
+
+            // back to where the table was opened, a nested table leaves
+            // a placeholder in the cell of its parent table
+            (_, Event::TableEnd { id, .. }) => {
+                if let Some(open_table) = self.table_stack.pop() {
+                    self.state = open_table.outer_state;
+                    self.text_buffer = open_table.outer_text_buffer;
+                    self.buffer_segments = open_table.outer_buffer_segments;
+                    if !self.table_stack.is_empty() {
+                        // the placeholder points at the `\n{|` of the table
+                        self.buffer_segments
+                            .push((self.text_buffer.len(), open_table.start));
+                        self.text_buffer += "\n";
+                        self.text_buffer += &nested_table_placeholder(id);
+                    }
+                }
+            }
 
             // Else
             (_, _) => {}
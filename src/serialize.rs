@@ -0,0 +1,205 @@
+use crate::document::protected_regions;
+use crate::parser::{cell_style_len, nested_table_placeholder, CellType, Event};
+use crate::table::{tables_from_events, Table};
+use crate::tokenizer::{
+    get_all_cell_text_special_tokens, get_all_table_special_tokens, CellTextSpecialTokens,
+    TableSpecialTokens, Token, Tokenizer, TypedTokenizer,
+};
+use std::sync::OnceLock;
+
+// Write tables back to wikitext, in one canonical form: one cell on each
+// line, a `|-` before every row and the attributes separated by ` | `.
+// Parsing the output gives the same tables, up to the whitespace around
+// the text and the attributes, and the `<nowiki>` around the table markup
+// of the text.
+
+// The text with its table markup in `<nowiki>`, e.g. `a <nowiki>||</nowiki> b`
+// or a line which starts with `!`, so that it is read back as text. The
+// `<!--` of an unclosed comment is escaped too, otherwise it would hide
+// the rest of the table once written.
+pub(crate) fn escape_markup(text: &str) -> String {
+    static TABLE_TOKENIZER: OnceLock<TypedTokenizer<TableSpecialTokens>> = OnceLock::new();
+    let table_tokenizer = TABLE_TOKENIZER
+        .get_or_init(|| TypedTokenizer::new(Tokenizer::build(get_all_table_special_tokens())));
+    // an unclosed comment runs to the end of the text, it protects nothing
+    let (regions, unclosed): (Vec<_>, Vec<_>) =
+        protected_regions(text).into_iter().partition(|region| {
+            let span = &text[region.clone()];
+            !span.starts_with("<!--") || (span.len() >= 7 && span.ends_with("-->"))
+        });
+    let unclosed_comment = unclosed.first().map(|region| region.start);
+    let mut out = String::new();
+    let mut offset = 0;
+    for token in table_tokenizer.tokens(text) {
+        let token_start = offset;
+        offset += token.as_str().len();
+        use TableSpecialTokens::*;
+        let markup = match token {
+            Token::Special(CommentStart) if unclosed_comment == Some(token_start) => {
+                Some(CommentStart)
+            }
+            Token::Special(
+                NoWikiStart | NoWikiEnd | CommentStart | CommentEnd | PreStart | PreEnd,
+            )
+            | Token::Text(_) => None,
+            Token::Special(markup) => Some(markup),
+        };
+        match markup {
+            Some(markup) if !regions.iter().any(|region| region.contains(&token_start)) => {
+                // the `\n` stays out, so the line still starts there
+                let markup = markup.as_ref();
+                let (newline, markup) = match markup.strip_prefix('\n') {
+                    Some(markup) => ("\n", markup),
+                    None => ("", markup),
+                };
+                out.push_str(newline);
+                out.push_str("<nowiki>");
+                out.push_str(markup);
+                out.push_str("</nowiki>");
+            }
+            _ => out.push_str(token.as_str()),
+        }
+    }
+    out
+}
+
+// the attributes without the `|` which ends a cell style
+fn clean_attrs(attrs: &str) -> &str {
+    let attrs = attrs.trim();
+    attrs.strip_suffix('|').unwrap_or(attrs).trim_end()
+}
+
+// The attributes on one line, with their table markup in `<nowiki>`. A
+// comment left open may have taken the lines after them.
+fn escape_attrs(attrs: &str) -> String {
+    escape_markup(&attrs.replace('\n', " "))
+}
+
+// Whether a `|` of the text of a cell or a caption would be read as the
+// end of its attributes, e.g. `x=1|[[b|c]]`.
+pub(crate) fn has_style_separator(text: &str) -> bool {
+    static CELL_TOKENIZER: OnceLock<TypedTokenizer<CellTextSpecialTokens>> = OnceLock::new();
+    let cell_tokenizer = CELL_TOKENIZER
+        .get_or_init(|| TypedTokenizer::new(Tokenizer::build(get_all_cell_text_special_tokens())));
+    cell_style_len(cell_tokenizer, text) > 0
+}
+
+// The attributes of a cell or a caption with the `|` after them. Without
+// attributes the `|` is still written if the text has one of its own,
+// otherwise it would be read as the attributes.
+fn write_attrs(out: &mut String, attrs: &str, text: &str) {
+    let attrs = clean_attrs(attrs);
+    if !attrs.is_empty() {
+        out.push(' ');
+        out.push_str(&escape_attrs(attrs));
+        out.push_str(" |");
+        return;
+    }
+    if has_style_separator(&escape_markup(text.trim())) {
+        out.push_str(" |");
+    }
+}
+
+// the text of a cell of `table`, with its nested tables
+fn write_text(out: &mut String, text: &str, table: &Table, tables: &[Table]) {
+    let mut rest = text.trim();
+    // the text after a nested table goes on the next line
+    let mut after_table = false;
+    loop {
+        let (before, nested) = match table.find_child_placeholder(rest) {
+            Some((id, start, end)) => (rest[..start].trim(), Some((id, end))),
+            None => (rest, None),
+        };
+        if !before.is_empty() {
+            // after a nested table, the text starts a line of its own
+            match after_table {
+                true => out.push_str(&escape_markup(&format!("\n{}", before))),
+                false => {
+                    out.push(' ');
+                    out.push_str(&escape_markup(before));
+                }
+            }
+        }
+        let Some((id, end)) = nested else {
+            break;
+        };
+        // a nested table starts on a line of its own
+        out.push('\n');
+        match tables.iter().find(|nested| nested.id == id) {
+            Some(nested) => write_table(out, nested, tables),
+            // the table is not given, the placeholder is kept
+            None => out.push_str(&nested_table_placeholder(id)),
+        }
+        rest = rest[end..].trim();
+        after_table = true;
+    }
+}
+
+// write the table, `tables` are looked up for the nested tables
+fn write_table(out: &mut String, table: &Table, tables: &[Table]) {
+    out.push_str("{|");
+    let attrs = clean_attrs(&table.attrs);
+    if !attrs.is_empty() {
+        out.push(' ');
+        out.push_str(&escape_attrs(attrs));
+    }
+    out.push('\n');
+    if let Some(caption) = &table.caption {
+        out.push_str("|+");
+        write_attrs(out, &table.caption_attrs, caption);
+        write_text(out, caption, table, tables);
+        out.push('\n');
+    }
+    for row in &table.rows {
+        out.push_str("|-");
+        // a nested table found before the first cell is written after the attributes
+        let (attrs, nested) = match table.find_child_placeholder(&row.attrs) {
+            Some((_, start, _)) => row.attrs.split_at(start),
+            None => (row.attrs.as_str(), ""),
+        };
+        let attrs = clean_attrs(attrs);
+        if !attrs.is_empty() {
+            out.push(' ');
+            out.push_str(&escape_attrs(attrs));
+        }
+        write_text(out, nested, table, tables);
+        out.push('\n');
+        for cell in &row.cells {
+            out.push(match cell.kind {
+                CellType::HeaderCell => '!',
+                CellType::DataCell => '|',
+            });
+            write_attrs(out, &cell.attrs, &cell.text);
+            write_text(out, &cell.text, table, tables);
+            out.push('\n');
+        }
+    }
+    out.push_str("|}");
+}
+
+/// The wikitext of one table. The placeholders of its nested tables are
+/// kept, see `tables_to_wikitext` to write them back.
+pub fn table_to_wikitext(table: &Table) -> String {
+    let mut out = String::new();
+    write_table(&mut out, table, &[]);
+    out
+}
+
+/// The wikitext of the tables as returned by `tables_from_events`. The
+/// nested tables are written in the cells they were found in, the top
+/// level tables are separated by an empty line.
+pub fn tables_to_wikitext(tables: &[Table]) -> String {
+    let mut out = String::new();
+    for table in tables.iter().filter(|table| table.depth == 0) {
+        if !out.is_empty() {
+            out.push_str("\n\n");
+        }
+        write_table(&mut out, table, tables);
+    }
+    out
+}
+
+/// The wikitext of the tables of the events.
+pub fn events_to_wikitext<I: IntoIterator<Item = Event>>(events: I) -> String {
+    tables_to_wikitext(&tables_from_events(events))
+}
@@ -0,0 +1,251 @@
+use crate::attributes::{parse_attributes, Attributes};
+use crate::inline::{parse_inline, Inline};
+use crate::parser::{
+    find_nested_table_placeholder, nested_table_placeholder, CellType, Event, WikitextTableParser,
+};
+use crate::tokenizer::{get_all_cell_text_special_tokens, get_all_table_special_tokens, Tokenizer};
+use pyo3::prelude::*;
+
+// An owned document model of the tables, built from the `Event` stream.
+
+#[pyclass]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Cell {
+    #[pyo3(get)]
+    pub kind: CellType,
+    #[pyo3(get)]
+    pub attrs: String,
+    #[pyo3(get)]
+    pub text: String,
+}
+
+#[pyclass]
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Row {
+    #[pyo3(get)]
+    pub attrs: String,
+    #[pyo3(get)]
+    pub cells: Vec<Cell>,
+}
+
+#[pyclass]
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Table {
+    // same as the `id` and `depth` of `Event::TableStart`
+    #[pyo3(get)]
+    pub id: usize,
+    #[pyo3(get)]
+    pub depth: usize,
+    #[pyo3(get)]
+    pub caption: Option<String>,
+    // the attributes of the caption, e.g. `style="color:red" |`
+    #[pyo3(get)]
+    pub caption_attrs: String,
+    #[pyo3(get)]
+    pub attrs: String,
+    #[pyo3(get)]
+    pub rows: Vec<Row>,
+    // the ids of the tables nested in this one, in the order they start. A
+    // placeholder with any other id is text written in the source.
+    #[pyo3(get)]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub children: Vec<usize>,
+}
+
+impl Cell {
+    pub fn attributes(&self) -> Attributes {
+        parse_attributes(&self.attrs)
+    }
+
+    /// The text parsed into links, templates, formatting and the like.
+    pub fn inline(&self) -> Vec<Inline> {
+        parse_inline(&self.text)
+    }
+}
+
+impl Row {
+    pub fn attributes(&self) -> Attributes {
+        parse_attributes(&self.attrs)
+    }
+}
+
+impl Table {
+    pub fn attributes(&self) -> Attributes {
+        parse_attributes(&self.attrs)
+    }
+
+    pub fn caption_attributes(&self) -> Attributes {
+        parse_attributes(&self.caption_attrs)
+    }
+
+    // The id and the byte range of the first placeholder of a table nested
+    // in this one. A placeholder of any other id is text, and a child
+    // starts after its parent, so writing the children always ends.
+    pub(crate) fn find_child_placeholder(&self, text: &str) -> Option<(usize, usize, usize)> {
+        find_child_placeholder(self.id, &self.children, text)
+    }
+
+    // Add `base` to the ids of the table and of its children, and to the ids
+    // of their placeholders, e.g. to put the tables of several parses in
+    // one list.
+    pub(crate) fn shift_ids(&mut self, base: usize) {
+        let mut texts: Vec<&mut String> = vec![&mut self.attrs, &mut self.caption_attrs];
+        texts.extend(self.caption.as_mut());
+        for row in self.rows.iter_mut() {
+            texts.push(&mut row.attrs);
+            for cell in row.cells.iter_mut() {
+                texts.push(&mut cell.attrs);
+                texts.push(&mut cell.text);
+            }
+        }
+        for text in texts {
+            let mut out = String::new();
+            let mut rest = text.as_str();
+            while let Some((child, start, end)) =
+                find_child_placeholder(self.id, &self.children, rest)
+            {
+                out += &rest[..start];
+                out += &nested_table_placeholder(child + base);
+                rest = &rest[end..];
+            }
+            *text = out + rest;
+        }
+        self.id += base;
+        for child in self.children.iter_mut() {
+            *child += base;
+        }
+    }
+}
+
+fn find_child_placeholder(
+    id: usize,
+    children: &[usize],
+    text: &str,
+) -> Option<(usize, usize, usize)> {
+    let mut offset = 0;
+    while let Some((child, start, end)) = find_nested_table_placeholder(&text[offset..]) {
+        if child > id && children.contains(&child) {
+            return Some((child, offset + start, offset + end));
+        }
+        offset += end;
+    }
+    None
+}
+
+/// Collect the events of `WikitextTableParser` into tables.
+///
+/// Tables are returned in the order they start, so the table referred by a
+/// nested table placeholder `<table-ref id="N"/>` is found at index `N`.
+/// Only the ids in `children` of a table are its nested tables, the text of
+/// a cell could have a placeholder of its own.
+pub fn tables_from_events<I: IntoIterator<Item = Event>>(events: I) -> Vec<Table> {
+    let mut tables: Vec<Option<Table>> = Vec::new();
+    let mut open_tables: Vec<Table> = Vec::new();
+    // the kind and the attributes of the open cell of each open table, a
+    // nested table is read between the start and the end of a cell
+    let mut open_cells: Vec<(CellType, String)> = Vec::new();
+
+    for event in events {
+        match event {
+            Event::TableStart { id, depth, .. } => {
+                if let Some(parent) = open_tables.last_mut() {
+                    parent.children.push(id);
+                }
+                open_tables.push(Table {
+                    id,
+                    depth,
+                    ..Default::default()
+                });
+                open_cells.push((CellType::DataCell, String::new()));
+            }
+            Event::TableStyle { text, .. } => {
+                if let Some(table) = open_tables.last_mut() {
+                    table.attrs = text;
+                }
+            }
+            Event::TableCaptionStyle { text, .. } => {
+                if let Some(table) = open_tables.last_mut() {
+                    table.caption_attrs = text;
+                }
+            }
+            // a `|-` right before a late `|+` does not make a row either
+            Event::TableCaptionStart { .. } => {
+                if let Some(table) = open_tables.last_mut() {
+                    if table.rows.last().is_some_and(|row| row.cells.is_empty()) {
+                        table.rows.pop();
+                    }
+                }
+            }
+            Event::TableCaption { text, .. } => {
+                if let Some(table) = open_tables.last_mut() {
+                    table.caption = Some(text);
+                }
+            }
+            Event::RowStart { .. } => {
+                if let Some(table) = open_tables.last_mut() {
+                    table.rows.push(Row::default());
+                }
+            }
+            Event::RowStyle { text, .. } => {
+                if let Some(row) = open_tables.last_mut().and_then(|t| t.rows.last_mut()) {
+                    row.attrs = text;
+                }
+            }
+            Event::ColStart { cell_type, .. } => {
+                if let Some(cell) = open_cells.last_mut() {
+                    *cell = (cell_type, String::new());
+                }
+            }
+            Event::ColStyle { text, .. } => {
+                if let Some((_, attrs)) = open_cells.last_mut() {
+                    *attrs = text;
+                }
+            }
+            Event::ColEnd { text, .. } => {
+                if let (Some(table), Some((kind, attrs))) =
+                    (open_tables.last_mut(), open_cells.last_mut())
+                {
+                    if table.rows.is_empty() {
+                        table.rows.push(Row::default());
+                    }
+                    let row = table.rows.last_mut().unwrap();
+                    row.cells.push(Cell {
+                        kind: kind.clone(),
+                        attrs: std::mem::take(attrs),
+                        text,
+                    });
+                }
+            }
+            Event::TableEnd { .. } => {
+                open_cells.pop();
+                if let Some(mut table) = open_tables.pop() {
+                    // a `|-` right before `|}` does not make a row, but the
+                    // rows covered by the rowspans above them are kept
+                    if table.rows.last().is_some_and(|row| row.cells.is_empty()) {
+                        table.rows.pop();
+                    }
+                    let id = table.id;
+                    if tables.len() <= id {
+                        tables.resize(id + 1, None);
+                    }
+                    tables[id] = Some(table);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    tables.into_iter().flatten().collect()
+}
+
+/// Parse every table in the wikitext.
+#[pyfunction]
+pub fn parse_tables(wikitext: &str) -> Vec<Table> {
+    let table_tokenizer = Tokenizer::build(get_all_table_special_tokens());
+    let cell_tokenizer = Tokenizer::build(get_all_cell_text_special_tokens());
+    let parser = WikitextTableParser::new(table_tokenizer, cell_tokenizer, wikitext, false);
+    tables_from_events(parser)
+}
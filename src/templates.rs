@@ -0,0 +1,406 @@
+use crate::document::protected_regions;
+use crate::inline::{plain_text, push_node, Inline, InlineParser, TemplateArg};
+use crate::utils::template_len;
+use std::fmt;
+use std::sync::Arc;
+
+// Expand the templates of the text of cells, e.g. `{{yes}}` or
+// `{{convert|5|km}}`, into the nodes they read as on the page. A template
+// which is not handled is kept as it is.
+// https://en.wikipedia.org/wiki/Help:Table#Templates
+
+/// Expands a template into nodes, or keeps it as it is with `None`.
+/// The templates of the arguments are already expanded.
+pub trait TemplateHandler: Send + Sync {
+    fn expand(&self, name: &str, args: &[TemplateArg]) -> Option<Vec<Inline>>;
+}
+
+impl<F> TemplateHandler for F
+where
+    F: Fn(&str, &[TemplateArg]) -> Option<Vec<Inline>> + Send + Sync,
+{
+    fn expand(&self, name: &str, args: &[TemplateArg]) -> Option<Vec<Inline>> {
+        self(name, args)
+    }
+}
+
+// the first handler which expands the template
+impl<A: TemplateHandler, B: TemplateHandler> TemplateHandler for (A, B) {
+    fn expand(&self, name: &str, args: &[TemplateArg]) -> Option<Vec<Inline>> {
+        self.0
+            .expand(name, args)
+            .or_else(|| self.1.expand(name, args))
+    }
+}
+
+/// Keeps every template as it is.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Passthrough;
+
+impl TemplateHandler for Passthrough {
+    fn expand(&self, _name: &str, _args: &[TemplateArg]) -> Option<Vec<Inline>> {
+        None
+    }
+}
+
+/// The templates most common in the tables of Wikipedia:
+///
+/// - `{{flagicon|USA}}` reads as nothing, `{{flag|Japan}}` as a link
+/// - `{{convert|5|km}}` as `5 km`, the value is not converted
+/// - `{{yes}}`, `{{no}}`, `{{partial}}`, `{{n/a}}`, `{{tba}}` as their
+///   text, or their first argument
+/// - `{{dts|2001|5|12}}` as `May 12, 2001`
+/// - `{{sort|key|text}}` and `{{nts|5}}` as their text
+/// - `{{sortname|John|Smith}}` as a link to `John Smith`
+/// - `{{nowrap|...}}`, `{{small|...}}` and `{{abbr|...}}` as their first
+///   argument
+/// - the footnotes, e.g. `{{efn|...}}` or `{{citation needed}}`, as nothing
+/// - `{{!}}`, `{{=}}`, `{{nbsp}}`, `{{ndash}}` and `{{mdash}}` as their
+///   character
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BuiltinTemplates;
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+fn text(text: &str) -> Vec<Inline> {
+    vec![Inline::Text(text.to_string())]
+}
+
+// the nodes without the spaces around them
+fn trim_nodes(nodes: &[Inline]) -> Vec<Inline> {
+    let mut nodes = nodes.to_vec();
+    if let Some(Inline::Text(first)) = nodes.first_mut() {
+        *first = first.trim_start().to_string();
+    }
+    if let Some(Inline::Text(last)) = nodes.last_mut() {
+        *last = last.trim_end().to_string();
+    }
+    nodes.retain(|node| node != &Inline::Text(String::new()));
+    nodes
+}
+
+fn positional(args: &[TemplateArg]) -> Vec<&[Inline]> {
+    args.iter()
+        .filter(|arg| arg.name.is_none())
+        .map(|arg| arg.value.as_slice())
+        .collect()
+}
+
+fn named<'a>(args: &'a [TemplateArg], key: &str) -> Option<&'a [Inline]> {
+    args.iter()
+        .find(|arg| arg.name.as_deref() == Some(key))
+        .map(|arg| arg.value.as_slice())
+}
+
+// the positional argument at `index` (0-based) as plain text
+fn arg_text(args: &[&[Inline]], index: usize) -> Option<String> {
+    args.get(index)
+        .map(|arg| plain_text(arg).trim().to_string())
+        .filter(|arg| !arg.is_empty())
+}
+
+// `{{convert|5|km}}`, or a range `{{convert|5|to|10|km}}`
+fn convert(args: &[&[Inline]]) -> Option<Vec<Inline>> {
+    let value = arg_text(args, 0)?;
+    let range = arg_text(args, 1).and_then(|sep| match sep.as_str() {
+        "-" | "–" => Some("–".to_string()),
+        "to" | "and" | "or" | "by" | "x" | "+/-" => Some(format!(" {} ", sep)),
+        _ => None,
+    });
+    let out = match range {
+        Some(sep) => format!(
+            "{}{}{} {}",
+            value,
+            sep,
+            arg_text(args, 2)?,
+            arg_text(args, 3)?
+        ),
+        None => format!("{} {}", value, arg_text(args, 1)?),
+    };
+    Some(text(&out))
+}
+
+fn month_name(month: &str) -> Option<&'static str> {
+    match month.parse::<usize>() {
+        Ok(number) => MONTHS.get(number.checked_sub(1)?).copied(),
+        Err(_) => MONTHS.iter().copied().find(|name| {
+            month.len() >= 3 && name.to_lowercase().starts_with(&month.to_lowercase())
+        }),
+    }
+}
+
+// `{{dts|2001|5|12}}`, `{{dts|2001-05-12}}` or `{{dts|May 2001}}`
+fn date(args: &[&[Inline]], day_first: bool) -> Option<Vec<Inline>> {
+    let first = arg_text(args, 0)?;
+    // the parts keep their position, an empty one is None
+    let parts: Vec<Option<String>> = match args.len() {
+        1 if first.split('-').count() == 3 => first
+            .split('-')
+            .map(|part| Some(part.to_string()).filter(|part| !part.is_empty()))
+            .collect(),
+        1 => return Some(text(&first)),
+        _ => (0..args.len().min(3)).map(|i| arg_text(args, i)).collect(),
+    };
+    let year = parts[0].as_deref()?;
+    // without its month, a day is not read, e.g. `{{dts|2001||12}}`
+    let month = match parts.get(1).and_then(Option::as_deref) {
+        Some(month) => month_name(month)?,
+        None => return Some(text(year)),
+    };
+    let day = parts
+        .get(2)
+        .and_then(Option::as_deref)
+        .map(|day| day.trim_start_matches('0'));
+    let out = match (day, day_first) {
+        (Some(day), true) => format!("{} {} {}", day, month, year),
+        (Some(day), false) => format!("{} {}, {}", month, day, year),
+        (None, _) => format!("{} {}", month, year),
+    };
+    Some(text(&out))
+}
+
+// `{{sortname|First|Last}}`, the third argument is the page it links to
+fn sortname(args: &[TemplateArg]) -> Option<Vec<Inline>> {
+    let positional = positional(args);
+    let name = [arg_text(&positional, 0), arg_text(&positional, 1)]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" ");
+    if name.is_empty() {
+        return None;
+    }
+    if named(args, "nolink").is_some() {
+        return Some(text(&name));
+    }
+    let node = match arg_text(&positional, 2) {
+        Some(target) => Inline::Link {
+            target,
+            anchor: None,
+            label: Some(text(&name)),
+        },
+        None => Inline::Link {
+            target: name,
+            anchor: None,
+            label: None,
+        },
+    };
+    Some(vec![node])
+}
+
+impl TemplateHandler for BuiltinTemplates {
+    fn expand(&self, name: &str, args: &[TemplateArg]) -> Option<Vec<Inline>> {
+        let name = name.replace('_', " ");
+        let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+        let name = name.to_lowercase();
+        let name = name.strip_prefix("template:").unwrap_or(&name).trim();
+        let positional = positional(args);
+        // the first positional argument, or the text the template reads as
+        let first_or = |default: &str| match positional.first() {
+            Some(arg) if !plain_text(arg).trim().is_empty() => trim_nodes(arg),
+            _ => text(default),
+        };
+        let first = || positional.first().map(|arg| trim_nodes(arg));
+        match name {
+            "flagicon" | "flagdeco" => Some(Vec::new()),
+            "flag" | "flagcountry" | "flagu" => {
+                let country = arg_text(&positional, 0)?;
+                Some(vec![Inline::Link {
+                    target: country,
+                    anchor: None,
+                    label: named(args, "name").map(trim_nodes),
+                }])
+            }
+            "convert" | "cvt" => convert(&positional),
+            "yes" => Some(first_or("Yes")),
+            "no" => Some(first_or("No")),
+            "partial" => Some(first_or("Partial")),
+            "n/a" | "na" => Some(first_or("N/A")),
+            "tba" => Some(first_or("TBA")),
+            "tbd" => Some(first_or("TBD")),
+            "unknown" => Some(first_or("Unknown")),
+            "dunno" => Some(first_or("?")),
+            "dts" | "dtsort" => {
+                let day_first =
+                    named(args, "format").is_some_and(|format| plain_text(format).trim() == "dmy");
+                date(&positional, day_first)
+            }
+            "sort" => positional
+                .get(1)
+                .or(positional.first())
+                .map(|arg| trim_nodes(arg)),
+            "nts" | "ntsp" => first(),
+            "hs" | "hidden sort key" | "ntsh" => Some(Vec::new()),
+            "sortname" => sortname(args),
+            "nowrap" | "nobr" | "small" | "smaller" | "big" | "abbr" | "abbrlink" => first(),
+            "efn"
+            | "efn-ua"
+            | "efn-lr"
+            | "refn"
+            | "sfn"
+            | "r"
+            | "citation needed"
+            | "cn"
+            | "fact"
+            | "better source needed"
+            | "clarify" => Some(Vec::new()),
+            "!" => Some(text("|")),
+            "=" => Some(text("=")),
+            "nbsp" => Some(text("\u{a0}")),
+            "ndash" | "–" => Some(text("–")),
+            "mdash" | "—" => Some(text("—")),
+            "snd" | "spaced ndash" => Some(text("\u{a0}– ")),
+            "dot" | "·" => Some(text("\u{a0}· ")),
+            _ => None,
+        }
+    }
+}
+
+/// Expand the templates of the nodes by the handler. The templates of the
+/// arguments of a template are expanded first.
+pub fn expand_templates(nodes: &[Inline], handler: &dyn TemplateHandler) -> Vec<Inline> {
+    let expand = |nodes: &[Inline]| expand_templates(nodes, handler);
+    let mut out = Vec::new();
+    for node in nodes {
+        let node = match node {
+            Inline::Template { name, args } => {
+                let args: Vec<TemplateArg> = args
+                    .iter()
+                    .map(|arg| TemplateArg {
+                        name: arg.name.clone(),
+                        value: expand(&arg.value),
+                    })
+                    .collect();
+                match handler.expand(name, &args) {
+                    Some(nodes) => {
+                        for node in nodes {
+                            push_node(&mut out, node);
+                        }
+                        continue;
+                    }
+                    None => Inline::Template {
+                        name: name.clone(),
+                        args,
+                    },
+                }
+            }
+            Inline::Link {
+                target,
+                anchor,
+                label,
+            } => Inline::Link {
+                target: target.clone(),
+                anchor: anchor.clone(),
+                label: label.as_deref().map(expand),
+            },
+            Inline::ExternalLink { url, label } => Inline::ExternalLink {
+                url: url.clone(),
+                label: label.as_deref().map(expand),
+            },
+            Inline::Bold(children) => Inline::Bold(expand(children)),
+            Inline::Italic(children) => Inline::Italic(expand(children)),
+            Inline::Element {
+                name,
+                attrs,
+                children,
+            } => Inline::Element {
+                name: name.clone(),
+                attrs: attrs.clone(),
+                children: expand(children),
+            },
+            Inline::Reference { name, content } => Inline::Reference {
+                name: name.clone(),
+                content: expand(content),
+            },
+            Inline::File {
+                target,
+                options,
+                caption,
+            } => Inline::File {
+                target: target.clone(),
+                options: options.clone(),
+                caption: caption.as_deref().map(expand),
+            },
+            Inline::Text(_) | Inline::NestedTable(_) => node.clone(),
+        };
+        push_node(&mut out, node);
+    }
+    out
+}
+
+/// Expands the templates of the wikitext of cells by a handler.
+#[derive(Clone)]
+pub struct TemplateExpander {
+    handler: Arc<dyn TemplateHandler>,
+    inline_parser: InlineParser,
+}
+
+impl fmt::Debug for TemplateExpander {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("TemplateExpander")
+    }
+}
+
+impl TemplateExpander {
+    pub fn new(handler: impl TemplateHandler + 'static) -> Self {
+        TemplateExpander::with_parser(handler, InlineParser::default())
+    }
+
+    /// The templates are read by the inline parser.
+    pub fn with_parser(
+        handler: impl TemplateHandler + 'static,
+        inline_parser: InlineParser,
+    ) -> Self {
+        TemplateExpander {
+            handler: Arc::new(handler),
+            inline_parser,
+        }
+    }
+
+    /// The text with each template replaced by the wikitext of what it is
+    /// expanded into. A template which is not expanded is kept as it was
+    /// written, and so is the text of comments, `<nowiki>` and `<pre>`.
+    pub fn expand_text(&self, text: &str) -> String {
+        let regions = protected_regions(text);
+        let mut out = String::new();
+        let mut last = 0;
+        let mut offset = 0;
+        while let Some(found) = text[offset..].find("{{") {
+            let start = offset + found;
+            if let Some(region) = regions.iter().find(|region| region.contains(&start)) {
+                offset = region.end;
+                continue;
+            }
+            let len = match template_len(&text[start..]) {
+                Some(len) => len,
+                // an unclosed template is kept as text
+                None => break,
+            };
+            let source = &text[start..start + len];
+            let nodes = self.inline_parser.parse(source);
+            let expanded = expand_templates(&nodes, self.handler.as_ref());
+            out += &text[last..start];
+            match expanded == nodes {
+                true => out += source,
+                false => out.extend(expanded.iter().map(|node| node.to_string())),
+            }
+            last = start + len;
+            offset = last;
+        }
+        out += &text[last..];
+        out
+    }
+}
@@ -1,5 +1,4 @@
-use core::fmt;
-use std::collections::HashMap;
+use std::str::FromStr;
 use strum::IntoEnumIterator;
 use strum_macros::AsRefStr;
 use strum_macros::EnumIter;
@@ -9,7 +8,7 @@ use pyo3::prelude::*;
 // Wiki wable special token markup definition
 // https://en.wikipedia.org/wiki/Help:Table#Basic_table_markup
 
-#[derive(Debug, PartialEq, EnumString, AsRefStr, EnumIter)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, AsRefStr, EnumIter)]
 pub enum TableSpecialTokens {
     // used in table
     #[strum(serialize = "\n{|")]
@@ -45,6 +44,18 @@ pub enum TableSpecialTokens {
 
     #[strum(serialize = "</nowiki>")]
     NoWikiEnd,
+
+    #[strum(serialize = "<!--")]
+    CommentStart,
+
+    #[strum(serialize = "-->")]
+    CommentEnd,
+
+    #[strum(serialize = "<pre>")]
+    PreStart,
+
+    #[strum(serialize = "</pre>")]
+    PreEnd,
 }
 
 #[pyfunction]
@@ -56,7 +67,7 @@ pub fn get_all_table_special_tokens() -> Vec<String> {
     out
 }
 
-#[derive(Debug, PartialEq, EnumString, AsRefStr, EnumIter)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, AsRefStr, EnumIter)]
 pub enum CellTextSpecialTokens {
     #[strum(serialize = "[[")]
     LinkStart,
@@ -70,6 +81,12 @@ pub enum CellTextSpecialTokens {
     TemplateEnd,
     #[strum(serialize = "'''")]
     Blockquote,
+    #[strum(serialize = "''")]
+    Italic,
+    #[strum(serialize = "[")]
+    ExternalLinkStart,
+    #[strum(serialize = "]")]
+    ExternalLinkEnd,
     #[strum(serialize = "<")]
     HtmlTagStart,
     #[strum(serialize = ">")]
@@ -85,119 +102,199 @@ pub fn get_all_cell_text_special_tokens() -> Vec<String> {
     out
 }
 
-#[derive(Debug, Clone)]
-pub struct TokenParseTreeNode {
-    val: char,
-    children: HashMap<char, TokenParseTreeNode>,
+// A node of the byte trie of the special tokens.
+#[derive(Debug, Clone, Default)]
+struct TrieNode {
+    children: Vec<(u8, usize)>,
+    // the index of the special token ending at this node
+    token: Option<usize>,
 }
 
 #[pyclass]
 #[derive(Debug,Clone)]
 pub struct Tokenizer {
-    token_tree: TokenParseTreeNode,
+    special_tokens: Vec<String>,
+    nodes: Vec<TrieNode>,
+    // whether a special token starts with the byte
+    first_bytes: [bool; 256],
 }
 
-impl fmt::Display for TokenParseTreeNode {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let mut children_vals: Vec<char> = Vec::new();
-        let mut out = String::new();
-        out = out + format!("val: {}", self.val).as_str();
-        for child in self.children.iter() {
-            children_vals.push(child.0.clone());
-        }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    // the index of the special token in the list the tokenizer is built from
+    Special(usize),
+    // the text between two special tokens
+    Text,
+}
 
-        write!(f, "{}\n  children{:?}", out, children_vals)
-    }
+/// The tokens of a text, borrowed from the text. Returned by `Tokenizer::tokens`.
+#[derive(Debug, Clone)]
+pub struct Tokens<'t, 's> {
+    tokenizer: &'t Tokenizer,
+    text: &'s str,
+    offset: usize,
 }
 
 #[pymethods]
 impl Tokenizer {
     #[new]
     pub fn build(special_tokens:Vec<String>) -> Self {
-
-        let mut root_node = TokenParseTreeNode {
-            val: '$', // a root's val is unused
-            children: HashMap::new(),
-        };
-
-        for token in special_tokens {
-            // println!("Token:{}", token);
-
-            let mut node = &mut root_node;
-            for t_char in token.chars() {
-                match node.clone().children.get(&t_char) {
-                    Some(_) => {
-                        // if a value is alirady in children
-                        // forward the node to the child.
-                        node = node.children.get_mut(&t_char).unwrap();
-                        // println!("\t exist:  {}", t_char);
-                    }
+        let mut nodes = vec![TrieNode::default()];
+        let mut first_bytes = [false; 256];
+        for (index, token) in special_tokens.iter().enumerate() {
+            if token.is_empty() {
+                continue;
+            }
+            first_bytes[token.as_bytes()[0] as usize] = true;
+            let mut node = 0;
+            for byte in token.bytes() {
+                node = match nodes[node].children.iter().find(|(b, _)| *b == byte) {
+                    Some((_, child)) => *child,
                     None => {
-                        // finally we reach the bottom of the tree branch,
-                        // insert the value in it
-                        node.children.insert(
-                            t_char.clone(),
-                            TokenParseTreeNode {
-                                val: t_char.clone(),
-                                children: HashMap::new(),
-                            },
-                        );
-                        node = node.children.get_mut(&t_char).unwrap();
-                        // println!("\t insert: {}", t_char);
+                        nodes.push(TrieNode::default());
+                        let child = nodes.len() - 1;
+                        nodes[node].children.push((byte, child));
+                        child
                     }
-                }
+                };
             }
-            // root_node = node;
+            // the first one wins if a token is given twice
+            nodes[node].token.get_or_insert(index);
+        }
+
+        Tokenizer {
+            special_tokens,
+            nodes,
+            first_bytes,
         }
-        // println!("----------");
-        // println!("{:}", root_node);
-        return Tokenizer {
-            token_tree: root_node,
-        };
     }
 
+    /// The special tokens, and the text between them split into chars. The
+    /// start of a special token which is not completed, e.g. the `</` of
+    /// `</x`, is split into chars too.
     pub fn tokenize(&self, raw_str: &str) -> Vec<String> {
         let mut out: Vec<String> = Vec::new();
+        for (kind, text) in self.tokens(raw_str) {
+            match kind {
+                TokenKind::Special(_) => out.push(text.to_string()),
+                TokenKind::Text => out.extend(text.chars().map(String::from)),
+            }
+        }
+        out
+    }
+}
 
-        let mut node = &self.token_tree;
+impl Tokenizer {
+    /// Split the text into special tokens and the text runs between them,
+    /// without copying. The longest special token is matched.
+    pub fn tokens<'t, 's>(&'t self, text: &'s str) -> Tokens<'t, 's> {
+        Tokens {
+            tokenizer: self,
+            text,
+            offset: 0,
+        }
+    }
 
-        let mut tmp: String = String::new();
-        for t_char in raw_str.chars() {
-            match node.clone().children.get(&t_char) {
-                Some(_) => {
-                    node = node.children.get(&t_char).unwrap();
-                    tmp = tmp + &node.val.to_string();
-                }
-                None => {
-                    if tmp != "" {
-                        out.push(tmp.clone());
-                    }
-                    // start from head for nex loop
-                    node = &self.token_tree;
-
-                    // process t_char which is this step
-                    match node.children.get(&t_char) {
-                        Some(_) => {
-                            // if is a root's child
-                            // forword to child
-                            tmp = String::from(&t_char.to_string());
-                            node = &self.token_tree.children.get(&t_char).unwrap();
-                        }
-                        None => {
-                            // else, clean tmp and push the data to out
-                            tmp = String::new();
-                            out.push(String::from(&t_char.to_string()));
-                        }
-                    }
+    pub fn special_tokens(&self) -> &[String] {
+        &self.special_tokens
+    }
+
+    // the longest special token at the start of the bytes, (index, length)
+    fn match_token(&self, bytes: &[u8]) -> Option<(usize, usize)> {
+        let mut node = 0;
+        let mut found = None;
+        for (i, byte) in bytes.iter().enumerate() {
+            node = match self.nodes[node].children.iter().find(|(b, _)| b == byte) {
+                Some((_, child)) => *child,
+                None => break,
+            };
+            if let Some(index) = self.nodes[node].token {
+                found = Some((index, i + 1));
+            }
+        }
+        found
+    }
+}
+
+impl<'s> Iterator for Tokens<'_, 's> {
+    type Item = (TokenKind, &'s str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let bytes = self.text.as_bytes();
+        let start = self.offset;
+        if start >= bytes.len() {
+            return None;
+        }
+
+        // a special token starts with a leading byte of UTF-8, so it is
+        // only matched at a char boundary
+        for i in start..bytes.len() {
+            if !self.tokenizer.first_bytes[bytes[i] as usize] {
+                continue;
+            }
+            if let Some((index, len)) = self.tokenizer.match_token(&bytes[i..]) {
+                if i > start {
+                    self.offset = i;
+                    return Some((TokenKind::Text, &self.text[start..i]));
                 }
+                self.offset = i + len;
+                return Some((TokenKind::Special(index), &self.text[i..i + len]));
             }
         }
+        self.offset = bytes.len();
+        Some((TokenKind::Text, &self.text[start..]))
+    }
+}
+
+/// A token whose special token is parsed as `K`, e.g. `TableSpecialTokens`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Token<'s, K> {
+    Special(K),
+    // the text between two special tokens, or a special token which is not a `K`
+    Text(&'s str),
+}
 
-        if tmp != "" {
-            out.push(tmp);
+impl<K: AsRef<str>> Token<'_, K> {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Token::Special(kind) => kind.as_ref(),
+            Token::Text(text) => text,
         }
+    }
+}
+
+/// A `Tokenizer` which emits `Token<K>` instead of strings.
+#[derive(Debug, Clone)]
+pub struct TypedTokenizer<K> {
+    tokenizer: Tokenizer,
+    // the kind of each special token of the tokenizer
+    kinds: Vec<Option<K>>,
+}
+
+impl<K: FromStr + Copy> TypedTokenizer<K> {
+    pub fn new(tokenizer: Tokenizer) -> Self {
+        let kinds = tokenizer
+            .special_tokens
+            .iter()
+            .map(|token| K::from_str(token).ok())
+            .collect();
+        TypedTokenizer { tokenizer, kinds }
+    }
+
+    pub fn tokenizer(&self) -> &Tokenizer {
+        &self.tokenizer
+    }
 
-        // println!("{:?}", out);
-        return out;
+    pub fn tokens<'t, 's>(&'t self, text: &'s str) -> impl Iterator<Item = Token<'s, K>> + 't
+    where
+        's: 't,
+    {
+        self.tokenizer.tokens(text).map(|(kind, text)| match kind {
+            TokenKind::Special(index) => match self.kinds[index] {
+                Some(kind) => Token::Special(kind),
+                None => Token::Text(text),
+            },
+            TokenKind::Text => Token::Text(text),
+        })
     }
 }
@@ -1,11 +1,213 @@
-use regex::Regex;
+use crate::inline::{link_label, link_text, parse_inline, write_nodes, Inline, InlineWriter};
+use pyo3::prelude::*;
+use regex::{Captures, Regex};
+use std::sync::OnceLock;
 
+// Turn the wikitext of a cell into plain text, as it reads on the page.
+// https://www.mediawiki.org/wiki/Help:Formatting
+
+/// What the cleaner does with the templates `{{...}}` of the text.
+#[pyclass]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TemplatePolicy {
+    // left as it is, e.g. `{{convert|1|km}}`
+    #[default]
+    Keep,
+    // removed, e.g. a `{{citation needed}}`
+    Remove,
+    // the unnamed arguments joined by a space, e.g. `John Smith` for
+    // `{{sortname|John|Smith}}`
+    Arguments,
+}
+
+fn regex(cell: &'static OnceLock<Regex>, pattern: &str) -> &'static Regex {
+    cell.get_or_init(|| Regex::new(pattern).unwrap())
+}
+
+fn decode_entity(entity: &str) -> Option<String> {
+    if let Some(number) = entity.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        return char::from_u32(code).map(String::from);
+    }
+    let c = match entity {
+        "amp" => "&",
+        "lt" => "<",
+        "gt" => ">",
+        "quot" => "\"",
+        "apos" => "'",
+        "nbsp" => "\u{a0}",
+        "ensp" => "\u{2002}",
+        "emsp" => "\u{2003}",
+        "thinsp" => "\u{2009}",
+        "shy" => "",
+        "ndash" => "–",
+        "mdash" => "—",
+        "minus" => "−",
+        "times" => "×",
+        "divide" => "÷",
+        "plusmn" => "±",
+        "deg" => "°",
+        "middot" => "·",
+        "bull" => "•",
+        "hellip" => "…",
+        "prime" => "′",
+        "Prime" => "″",
+        "lsquo" => "‘",
+        "rsquo" => "’",
+        "ldquo" => "“",
+        "rdquo" => "”",
+        "laquo" => "«",
+        "raquo" => "»",
+        "frac12" => "½",
+        "frac14" => "¼",
+        "frac34" => "¾",
+        "sup2" => "²",
+        "sup3" => "³",
+        "micro" => "µ",
+        "euro" => "€",
+        "pound" => "£",
+        "yen" => "¥",
+        "cent" => "¢",
+        "copy" => "©",
+        "reg" => "®",
+        "trade" => "™",
+        "sect" => "§",
+        "dagger" => "†",
+        "Dagger" => "‡",
+        _ => return None,
+    };
+    Some(c.to_string())
+}
+
+/// Decode the HTML entities of the text, e.g. `&amp;`, `&#8211;` or
+/// `&#x2013;`. An unknown entity is kept as it is.
+pub fn decode_entities(text: &str) -> String {
+    static ENTITY: OnceLock<Regex> = OnceLock::new();
+    regex(
+        &ENTITY,
+        r"&(#[0-9]+|#[xX][0-9a-fA-F]+|[a-zA-Z][a-zA-Z0-9]*);",
+    )
+    .replace_all(text, |caps: &Captures| {
+        decode_entity(&caps[1]).unwrap_or_else(|| caps[0].to_string())
+    })
+    .into_owned()
+}
+
+// The byte length of the template at the start of the text, up to and
+// including its `}}`. Templates may be nested.
+pub(crate) fn template_len(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut i = 0;
+    while i < text.len() {
+        if text[i..].starts_with("{{") {
+            depth += 1;
+            i += 2;
+        } else if text[i..].starts_with("}}") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                return Some(i);
+            }
+        } else {
+            i += text[i..].chars().next().unwrap().len_utf8();
+        }
+    }
+    None
+}
+
+// Writes the nodes as plain text, the templates as `TemplatePolicy` says.
+struct PlainTextWriter {
+    templates: TemplatePolicy,
+}
+
+impl InlineWriter for PlainTextWriter {
+    fn write_node(&self, out: &mut String, node: &Inline) -> bool {
+        match node {
+            // a category link has no text on the page
+            Inline::Link { target, .. }
+                if target.split_once(':').is_some_and(|(namespace, _)| {
+                    namespace.trim().eq_ignore_ascii_case("category")
+                }) => {}
+            Inline::Link {
+                target,
+                anchor,
+                label,
+            } => match link_label(label) {
+                Some(label) => write_nodes(out, label, self),
+                None => self.write_text(out, &link_text(target, anchor, label)),
+            },
+            Inline::ExternalLink { label, .. } => {
+                if let Some(label) = label {
+                    write_nodes(out, label, self);
+                }
+            }
+            // `{{!}}` is how a `|` is written in a template
+            Inline::Template { name, .. }
+                if name.trim() == "!" && self.templates != TemplatePolicy::Keep =>
+            {
+                out.push('|')
+            }
+            Inline::Template { .. } if self.templates == TemplatePolicy::Keep => return false,
+            Inline::Template { args, .. } => {
+                if self.templates == TemplatePolicy::Arguments {
+                    let args: Vec<String> = args
+                        .iter()
+                        .filter(|arg| arg.name.is_none())
+                        .map(|arg| {
+                            let mut text = String::new();
+                            write_nodes(&mut text, &arg.value, self);
+                            text
+                        })
+                        .filter(|arg| !arg.trim().is_empty())
+                        .collect();
+                    *out += &args.join(" ");
+                }
+            }
+            Inline::Bold(children) | Inline::Italic(children) => write_nodes(out, children, self),
+            Inline::Element { name, .. } if name == "br" => out.push('\n'),
+            Inline::Element { children, .. } => write_nodes(out, children, self),
+            Inline::Reference { .. } | Inline::File { .. } => {}
+            Inline::Text(_) | Inline::NestedTable(_) => return false,
+        }
+        true
+    }
+
+    fn write_text(&self, out: &mut String, text: &str) {
+        *out += &decode_entities(text);
+    }
+}
+
+/// The plain text of the wikitext of a cell: the label of the links, no
+/// bold and italic, a line break for `<br>`, no references, comments or
+/// other tags, and the HTML entities decoded. The templates are kept, see
+/// `clean_col_text_with_policy` to remove them.
 pub fn clean_col_text(text: &str) -> String {
-    let mut clean_text = text.trim().to_string();
-    clean_text = Regex::new(r"^(\||\!)+|(\||\!)+$")
-        .unwrap()
-        .replace_all(&clean_text, "")
-        .trim()
-        .to_string();
-    return  clean_text;
+    clean_col_text_with_policy(text, TemplatePolicy::default())
+}
+
+/// `clean_col_text` with the templates handled by `templates`.
+pub fn clean_col_text_with_policy(text: &str, templates: TemplatePolicy) -> String {
+    let mut out = String::new();
+    write_nodes(
+        &mut out,
+        &parse_inline(text),
+        &PlainTextWriter { templates },
+    );
+
+    // the spaces left by the removed markup
+    let out = out
+        .lines()
+        .map(|line| {
+            line.split([' ', '\t'])
+                .filter(|word| !word.is_empty())
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    // the `|` and `!` left around the text by the table markup
+    out.trim().trim_matches(['|', '!']).trim().to_string()
 }